@@ -0,0 +1,309 @@
+//! Maintain the beatmaps without the window.
+//!
+//! Exit code: 0 if all passed, 1 if some beatmap failed, 2 for the usage or io error.
+
+use anyhow::anyhow;
+use log::LevelFilter;
+use rr_core::game::OffsetType;
+use rr_core::game::beatmap::BEATMAP_EXT;
+use rr_core::game::beatmap::file::SongBeatmapFile;
+use rr_core::game::beatmap::lint::{Severity, lint_beatmap};
+use rr_core::game::beatmap::osu::{OSU_EXT, export_osu, parse_osu};
+use rr_core::game::beatmap::sm::{SM_EXTS, parse_sm};
+use rr_core::game::beatmap::stats::BeatmapStats;
+use rr_core::game::song::{SongInfo, SongMetadata, sanitize_file_name};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: rust_rhythm_cli <command> [options]
+
+Commands:
+  validate [--strict] [root]       Check every .rr under the root (default: songs),
+                                   --strict fails on warnings too
+  stats <path>...                  Print the stats of the beatmaps
+  convert [--difficulty <name>] <input> <output>
+                                   Convert .osu/.sm/.ssc into .rr, or .rr into .osu,
+                                   the output is a dir if the input has many difficulties
+  fmt [--check] <path>...          Re-save the beatmaps in canonical pretty RON,
+                                   --check only reports the files to re-save";
+
+const EXIT_FAILED: u8 = 1;
+const EXIT_ERROR: u8 = 2;
+
+struct Args {
+    positional: Vec<String>,
+    flags: HashSet<String>,
+    options: HashMap<String, String>,
+}
+
+/// Parse the args, `flags` take no value and `options` take one value.
+fn parse_args(args: &[String], flags: &[&str], options: &[&str]) -> anyhow::Result<Args> {
+    let mut result = Args {
+        positional: vec![],
+        flags: HashSet::new(),
+        options: HashMap::new(),
+    };
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        if flags.contains(&arg.as_str()) {
+            result.flags.insert(arg.clone());
+        } else if options.contains(&arg.as_str()) {
+            let value = it.next().ok_or(anyhow!("Missing value for {}", arg))?;
+            result.options.insert(arg.clone(), value.clone());
+        } else if arg.starts_with("--") {
+            return Err(anyhow!("Unknown option {}\n\n{}", arg, USAGE));
+        } else {
+            result.positional.push(arg.clone());
+        }
+    }
+    Ok(result)
+}
+
+fn get_ext(path: &Path) -> String {
+    path.extension()
+        .map(|x| x.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Collect the beatmap files, the dirs are walked recursively.
+fn collect_beatmaps(path: &Path, result: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    if path.is_dir() {
+        let mut entries = std::fs::read_dir(path)?
+            .map(|x| x.map(|x| x.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for entry in entries {
+            if entry.is_dir() || get_ext(&entry) == BEATMAP_EXT {
+                collect_beatmaps(&entry, result)?;
+            }
+        }
+    } else if path.is_file() {
+        result.push(path.to_path_buf());
+    } else {
+        return Err(anyhow!("Cannot find {:?}", path));
+    }
+    Ok(())
+}
+
+fn collect_all_beatmaps(paths: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    if paths.is_empty() {
+        return Err(anyhow!("No path given\n\n{}", USAGE));
+    }
+    let mut result = vec![];
+    for path in paths {
+        collect_beatmaps(Path::new(path), &mut result)?;
+    }
+    Ok(result)
+}
+
+fn load_beatmap(path: &Path) -> anyhow::Result<SongBeatmapFile> {
+    let mut beatmap = SongBeatmapFile::load_from_bytes(&std::fs::read(path)?)?;
+    beatmap.update();
+    Ok(beatmap)
+}
+
+fn format_ms(ms: OffsetType) -> String {
+    let sign = if ms < 0 { "-" } else { "" };
+    let ms = ms.abs();
+    format!(
+        "{}{}:{:02}.{:03}",
+        sign,
+        ms / 60000,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+fn validate(args: &[String]) -> anyhow::Result<bool> {
+    let args = parse_args(args, &["--strict"], &[])?;
+    let strict = args.flags.contains("--strict");
+    let root = args
+        .positional
+        .first()
+        .map(String::as_str)
+        .unwrap_or("songs");
+    let files = collect_all_beatmaps(&[root.to_string()])?;
+
+    let mut failed = 0;
+    for file in &files {
+        match load_beatmap(file) {
+            Ok(beatmap) => {
+                let diagnostics = lint_beatmap(&beatmap, None);
+                let fail = diagnostics
+                    .iter()
+                    .any(|x| strict || x.severity == Severity::Error);
+                if fail {
+                    failed += 1;
+                }
+                if !diagnostics.is_empty() {
+                    println!("{} {}", if fail { "FAIL" } else { "WARN" }, file.display());
+                    for diagnostic in diagnostics {
+                        println!("  {}", diagnostic);
+                    }
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("FAIL {}", file.display());
+                println!("  {:#}", e);
+            }
+        }
+    }
+    println!("{} beatmaps checked, {} failed", files.len(), failed);
+    Ok(failed == 0)
+}
+
+fn stats(args: &[String]) -> anyhow::Result<bool> {
+    let args = parse_args(args, &[], &[])?;
+    let mut passed = true;
+    for file in collect_all_beatmaps(&args.positional)? {
+        let beatmap = match load_beatmap(&file) {
+            Ok(x) => x,
+            Err(e) => {
+                passed = false;
+                println!("FAIL {}", file.display());
+                println!("  {:#}", e);
+                continue;
+            }
+        };
+        let stats = BeatmapStats::new(&beatmap);
+        println!("{}", file.display());
+        println!(
+            "  name:     {} ({:?})",
+            beatmap.get_show_name(),
+            beatmap.rule
+        );
+        println!(
+            "  notes:    {} (normal {}, long {})",
+            stats.get_note_count(),
+            stats.normal_notes,
+            stats.long_notes
+        );
+        println!(
+            "  duration: {} ({} - {})",
+            format_ms(stats.get_duration()),
+            format_ms(stats.first_time.unwrap_or(0)),
+            format_ms(stats.last_time.unwrap_or(0))
+        );
+        println!("  bpm:      {} - {}", stats.min_bpm, stats.max_bpm);
+        println!("  rating:   {:.2}", stats.rating);
+    }
+    Ok(passed)
+}
+
+/// Write the beatmaps into the output file, or the dir if many.
+fn write_beatmaps(beatmaps: &[SongBeatmapFile], output: &Path) -> anyhow::Result<()> {
+    if beatmaps.len() == 1 && get_ext(output) == BEATMAP_EXT {
+        beatmaps[0].save_to(output)?;
+        println!("{}", output.display());
+        return Ok(());
+    }
+    std::fs::create_dir_all(output)?;
+    for beatmap in beatmaps {
+        let path = output.join(sanitize_file_name(&beatmap.get_show_name()) + "." + BEATMAP_EXT);
+        beatmap.save_to(&path)?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+fn convert(args: &[String]) -> anyhow::Result<bool> {
+    let args = parse_args(args, &[], &["--difficulty"])?;
+    let [input, output] = &args.positional[..] else {
+        return Err(anyhow!("Need the input and the output\n\n{}", USAGE));
+    };
+    let (input, output) = (Path::new(input), Path::new(output));
+    let ext = get_ext(input);
+
+    if ext == OSU_EXT {
+        let beatmap = parse_osu(&std::fs::read_to_string(input)?)?.beatmap;
+        write_beatmaps(&[beatmap], output)?;
+    } else if SM_EXTS.contains(&ext.as_str()) {
+        let mut charts = parse_sm(&std::fs::read_to_string(input)?)?.charts;
+        if let Some(difficulty) = args.options.get("--difficulty") {
+            charts.retain(|x| x.metadata.version.eq_ignore_ascii_case(difficulty));
+            if charts.is_empty() {
+                return Err(anyhow!("No difficulty {} found", difficulty));
+            }
+        }
+        write_beatmaps(&charts, output)?;
+    } else if ext == BEATMAP_EXT {
+        let beatmap = load_beatmap(input)?;
+        // refer the bgm next to the beatmap.
+        let dir = input.parent().unwrap_or(Path::new("."));
+        let audio_filename = SongInfo::supported_bgm_format()
+            .iter()
+            .map(|ext| "bgm.".to_string() + ext)
+            .find(|x| dir.join(x).is_file())
+            .unwrap_or_else(|| "bgm.mp3".to_string());
+        let preview_time = SongMetadata::load(dir)?.and_then(|x| x.preview_start);
+        std::fs::write(output, export_osu(&beatmap, &audio_filename, preview_time)?)?;
+        println!("{}", output.display());
+    } else {
+        return Err(anyhow!("Unsupported format for {:?}", input));
+    }
+    Ok(true)
+}
+
+fn fmt(args: &[String]) -> anyhow::Result<bool> {
+    let args = parse_args(args, &["--check"], &[])?;
+    let check = args.flags.contains("--check");
+    let mut passed = true;
+    for file in collect_all_beatmaps(&args.positional)? {
+        let data = std::fs::read(&file)?;
+        let canonical =
+            match SongBeatmapFile::load_from_bytes(&data).and_then(|x| x.to_pretty_ron()) {
+                Ok(x) => x,
+                Err(e) => {
+                    passed = false;
+                    println!("FAIL {}", file.display());
+                    println!("  {:#}", e);
+                    continue;
+                }
+            };
+        if canonical.as_bytes() == data {
+            continue;
+        }
+        if check {
+            passed = false;
+            println!("Would re-save {}", file.display());
+        } else {
+            std::fs::write(&file, canonical)?;
+            println!("Re-saved {}", file.display());
+        }
+    }
+    Ok(passed)
+}
+
+fn main() -> ExitCode {
+    env_logger::builder()
+        .filter_level(LevelFilter::Warn)
+        .parse_default_env()
+        .init();
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let command_args = args.get(1..).unwrap_or_default();
+    let result = match args.first().map(String::as_str) {
+        Some("validate") => validate(command_args),
+        Some("stats") => stats(command_args),
+        Some("convert") => convert(command_args),
+        Some("fmt") => fmt(command_args),
+        Some("help" | "-h" | "--help") => {
+            println!("{}", USAGE);
+            Ok(true)
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(EXIT_FAILED),
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
@@ -12,8 +12,10 @@ use std::time::{Duration, Instant};
 
 enum ControlEvent {
     SetVol(f32),
+    SetSpeed(f32),
     Seek(Duration),
     Play,
+    Pause,
     Stop,
 }
 
@@ -30,6 +32,8 @@ struct ControlledSampleBuffers {
     update_left: u128,
     update_freq: u128,
     vol: f32,
+    /// The playback speed, changing the pitch with it.
+    speed: f32,
     stop: bool,
     pause: bool,
     /// The duration in ms
@@ -73,6 +77,7 @@ impl ControlledSampleBuffers {
         Self {
             buffer: buffer.track_position(),
             vol: 1.0,
+            speed: 1.0,
             stop: false,
             pause: true,
             shared,
@@ -89,6 +94,9 @@ impl ControlledSampleBuffers {
                     ControlEvent::SetVol(vol) => {
                         self.vol = vol;
                     }
+                    ControlEvent::SetSpeed(speed) => {
+                        self.speed = speed;
+                    }
                     ControlEvent::Seek(d) => {
                         self.buffer.try_seek(d).unwrap();
                         self.update_pos();
@@ -99,6 +107,9 @@ impl ControlledSampleBuffers {
                     ControlEvent::Play => {
                         self.pause = false;
                     }
+                    ControlEvent::Pause => {
+                        self.pause = true;
+                    }
                 },
                 Err(TryRecvError::Disconnected) => {
                     self.stop();
@@ -149,7 +160,8 @@ impl Source for ControlledSampleBuffers {
     }
 
     fn sample_rate(&self) -> u32 {
-        self.buffer.sample_rate()
+        // the output resamples every span, so the speed changes in the next span.
+        ((self.buffer.sample_rate() as f32 * self.speed).round() as u32).max(1)
     }
 
     fn total_duration(&self) -> Option<Duration> {
@@ -171,6 +183,7 @@ pub struct ControlledBufferHandle {
     tx: Sender<ControlEvent>,
     mem: SharedMem,
     vol: f32,
+    speed: f32,
 }
 
 impl ControlledBufferHandle {
@@ -184,7 +197,12 @@ impl ControlledBufferHandle {
             rx,
         );
         output.add(source);
-        let this = Self { tx, mem, vol: 1.0 };
+        let this = Self {
+            tx,
+            mem,
+            vol: 1.0,
+            speed: 1.0,
+        };
         Ok(this)
     }
 
@@ -197,9 +215,22 @@ impl ControlledBufferHandle {
         let _ = self.tx.send(ControlEvent::SetVol(vol));
     }
 
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Play faster or slower, the pitch changes with the speed.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+        let _ = self.tx.send(ControlEvent::SetSpeed(speed));
+    }
+
     pub fn get_pos(&self) -> Duration {
         let (base, time) = self.mem.duration.load();
-        base.add(time.map(|x| x.elapsed()).unwrap_or(Duration::ZERO))
+        base.add(
+            time.map(|x| x.elapsed().mul_f32(self.speed))
+                .unwrap_or(Duration::ZERO),
+        )
     }
 
     pub fn seek_to(&self, d: Duration) {
@@ -218,6 +249,11 @@ impl ControlledBufferHandle {
         self.tx.send(ControlEvent::Play)
             .expect("Failed to play.");
     }
+
+    /// Pause the playing, the position is kept until [`Self::play`].
+    pub fn pause(&self) {
+        let _ = self.tx.send(ControlEvent::Pause);
+    }
 }
 
 impl Drop for ControlledBufferHandle {
@@ -1,5 +1,5 @@
 use anyhow::{self, Ok};
-use toml_edit::{value, DocumentMut};
+use toml_edit::{value, Array, DocumentMut};
 
 #[allow(unused)]
 #[derive(Default, Debug, Clone)]
@@ -47,6 +47,20 @@ impl Config {
             }) as f32
     }
 
+    pub fn get_str_array_def(&mut self, key: &str, def: &[String]) -> Vec<String> {
+        match self.toml.get(key).and_then(|x| x.as_array()) {
+            Some(array) => array.iter().filter_map(|x| x.as_str()).map(str::to_string).collect(),
+            None => {
+                self.toml_mut().insert(key, value(def.iter().collect::<Array>()));
+                def.to_vec()
+            }
+        }
+    }
+
+    pub fn set_str_array(&mut self, key: &str, values: &[String]) {
+        self.toml_mut().insert(key, value(values.iter().collect::<Array>()));
+    }
+
     pub fn check_save(&mut self) {
         if self.is_dirty() {
             std::fs::write("cfg.toml", self.toml.to_string());
@@ -6,7 +6,7 @@ use std::collections::{HashMap, HashSet};
 use std::mem::swap;
 use std::str::FromStr;
 use winit::dpi::PhysicalPosition;
-use winit::event::{Touch, TouchPhase};
+use winit::event::{ElementState, MouseButton, Touch, TouchPhase, WindowEvent};
 use winit::keyboard::PhysicalKey;
 
 #[derive(Debug, Clone)]
@@ -16,16 +16,51 @@ pub struct Pointer {
     phase: TouchPhase,
 }
 
+/// The pointer id of the mouse button is with this bit, the touch id is masked by
+/// [`TOUCH_POINTER_MASK`], so they never conflict.
+pub const MOUSE_POINTER: u64 = 1 << 62;
+pub const TOUCH_POINTER_MASK: u64 = MOUSE_POINTER - 1;
+
 impl From<Touch> for Pointer {
     fn from(touch: Touch) -> Self {
         Self {
-            id: touch.id,
+            id: touch.id & TOUCH_POINTER_MASK,
             loc: touch.location,
             phase: touch.phase,
         }
     }
 }
 
+impl Pointer {
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn get_loc(&self) -> PhysicalPosition<f64> {
+        self.loc
+    }
+
+    pub fn get_phase(&self) -> TouchPhase {
+        self.phase
+    }
+
+    pub fn is_mouse(&self) -> bool {
+        self.id & MOUSE_POINTER != 0
+    }
+}
+
+pub fn get_mouse_pointer_id(button: MouseButton) -> u64 {
+    MOUSE_POINTER
+        | match button {
+            MouseButton::Left => 0,
+            MouseButton::Right => 1,
+            MouseButton::Middle => 2,
+            MouseButton::Back => 3,
+            MouseButton::Forward => 4,
+            MouseButton::Other(x) => 5 + x as u64,
+        }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct RawInputData {
     pub points: HashMap<usize, Pointer>,
@@ -64,9 +99,11 @@ pub struct BakedInputs {
     pub cur_temp_game_input: RawInputData,
     /// only swap in states.game tick
     pub last_temp_game_input: RawInputData,
+    /// The pressing mouse buttons and touches.
     pub points: HashMap<u64, Pointer>,
     pub pressed_any_cur_frame: usize,
     pub mouse_state: MouseState,
+    cursor: PhysicalPosition<f64>,
 }
 
 impl BakedInputs {
@@ -85,6 +122,45 @@ impl BakedInputs {
             }
         }
     }
+    /// Update the pointers by the window event, return the changed pointers. The ended pointers
+    /// are removed.
+    pub(in crate::engine) fn process_pointer_event(&mut self, event: &WindowEvent) -> Vec<Pointer> {
+        let mut changed = vec![];
+        match event {
+            WindowEvent::Touch(touch) => changed.push(Pointer::from(*touch)),
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = *position;
+                changed.extend(self.points.values().filter(|x| x.is_mouse()).map(|x| Pointer {
+                    id: x.id,
+                    loc: *position,
+                    phase: TouchPhase::Moved,
+                }));
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let id = get_mouse_pointer_id(*button);
+                let pressing = self.points.contains_key(&id);
+                let phase = match state {
+                    ElementState::Pressed if !pressing => TouchPhase::Started,
+                    ElementState::Released if pressing => TouchPhase::Ended,
+                    _ => return changed,
+                };
+                changed.push(Pointer {
+                    id,
+                    loc: self.cursor,
+                    phase,
+                });
+            }
+            _ => {}
+        }
+        for pointer in &changed {
+            match pointer.phase {
+                TouchPhase::Ended | TouchPhase::Cancelled => self.points.remove(&pointer.id),
+                _ => self.points.insert(pointer.id, pointer.clone()),
+            };
+        }
+        changed
+    }
+
     /// save current input to last
     /// make current temp input to current frame input
     pub(in crate::engine) fn swap_frame(&mut self) {
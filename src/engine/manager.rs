@@ -37,7 +37,7 @@ use crate::engine::app::AppInstance;
 use crate::engine::global::IO_POOL;
 use crate::engine::prelude::MaintainBase;
 use crate::engine::{
-    GameState, GlobalData, LoopState, MainRendererData, MouseState, Pointer, StateEvent, Trans,
+    GameState, GlobalData, LoopState, MainRendererData, MouseState, StateEvent, Trans,
     WgpuData,
 };
 
@@ -407,10 +407,16 @@ impl WindowInstance {
         self.loop_info.got_event = true;
         // let _ = self.app.egui.on_window_event(&self.app.window, we);
 
+        let pointers = self.app.inputs.process_pointer_event(we);
         let sd = &mut get_state!(self.app, wd);
         for x in &mut self.states {
             x.on_event(sd, StateEvent::Window(we, time));
         }
+        for pointer in &pointers {
+            for x in &mut self.states {
+                x.on_event(sd, StateEvent::Pointer(pointer, time));
+            }
+        }
         match we {
             WindowEvent::Touch(touch) => {
                 self.loop_info.mouse_input.pos =
@@ -429,10 +435,6 @@ impl WindowInstance {
                         self.loop_info.mouse_input.last_left_click = true;
                     }
                 }
-                self.app
-                    .inputs
-                    .points
-                    .insert(touch.id, Pointer::from(*touch));
             }
             WindowEvent::CursorMoved {
                 device_id,
@@ -15,6 +15,7 @@ pub use wait_future::*;
 
 use crate::engine::app::AppInstance;
 use crate::engine::manager::{EngineEventLoopProxy, EventLoopProxyType, EventLoopTargetType, WindowInstance};
+use crate::engine::Pointer;
 
 mod wait_future;
 
@@ -39,6 +40,9 @@ pub enum StateEvent<'a> {
     PostUiRender,
     Resume,
     Window(&'a WindowEvent, Instant),
+    /// The mouse button or touch pointer changed by the window event, see
+    /// [`crate::engine::BakedInputs::points`].
+    Pointer(&'a Pointer, Instant),
 }
 
 impl Default for Trans {
@@ -0,0 +1,194 @@
+//! Estimate how hard the beatmap is.
+//!
+//! Every chord (the notes at the same time) adds a value to the strain, and the strain decays
+//! over time. The value considers the chord size, jacks in the same key lane, the long notes being
+//! held and the x jumps in falling rule. The peak strain of every section makes the rating.
+
+use crate::game::OffsetType;
+use crate::game::beatmap::file::SongBeatmapFile;
+use crate::game::beatmap::get_lane;
+use crate::game::note::Note;
+use serde::{Deserialize, Serialize};
+
+/// The length of one strain section.
+pub const SECTION_MS: OffsetType = 1000;
+/// The strain left after one second.
+const STRAIN_DECAY_PER_SEC: f64 = 0.3;
+/// The weight decay of the sorted section strains.
+const SECTION_WEIGHT_DECAY: f64 = 0.9;
+const RATING_SCALE: f64 = 0.5;
+
+const CHORD_BONUS: f64 = 0.25;
+/// The jack interval that gets the full bonus.
+const JACK_FULL_MS: f64 = 150.0;
+const JACK_BONUS: f64 = 1.0;
+const HOLD_BONUS: f64 = 0.5;
+/// The jump interval that gets the full bonus.
+const JUMP_FULL_MS: f64 = 200.0;
+const JUMP_BONUS: f64 = 1.5;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DifficultyRating {
+    /// The overall rating, 0 if no notes.
+    pub rating: f32,
+    /// The peak strain of every [`SECTION_MS`] section from 0.
+    pub strains: Vec<f32>,
+}
+
+struct DiffNote {
+    time: OffsetType,
+    end_time: Option<OffsetType>,
+    x: f32,
+}
+
+/// Calculate the difficulty of the beatmap.
+pub fn calculate_difficulty(beatmap: &SongBeatmapFile) -> DifficultyRating {
+    let mut notes = beatmap
+        .normal_notes
+        .iter()
+        .map(|x| x as &dyn Note)
+        .chain(beatmap.long_notes.iter().map(|x| x as &dyn Note))
+        .map(|x| DiffNote {
+            time: x.get_time(),
+            end_time: x.get_end_time(),
+            x: x.get_x(),
+        })
+        .collect::<Vec<_>>();
+    if notes.is_empty() {
+        return DifficultyRating::default();
+    }
+    notes.sort_by(|a, b| a.time.cmp(&b.time).then(a.x.total_cmp(&b.x)));
+
+    let sections = (notes.last().unwrap().time.max(0) / SECTION_MS + 1) as usize;
+    let mut strains = vec![0.0f64; sections];
+
+    let mut strain = 0.0;
+    let mut last_time = None;
+    let keys = beatmap.rule.get_keys();
+    let mut lane_last_time: Vec<Option<OffsetType>> = vec![None; keys.unwrap_or(0) as usize];
+    let mut last_x = None;
+    let mut holding_ends: Vec<OffsetType> = vec![];
+
+    for chord in notes.chunk_by(|a, b| a.time == b.time) {
+        let time = chord[0].time;
+        let dt = last_time.map(|x| (time - x) as f64).unwrap_or(0.0);
+        strain *= STRAIN_DECAY_PER_SEC.powf(dt / 1000.0);
+
+        let size = chord.len() as f64;
+        let mut value = size * (1.0 + CHORD_BONUS * (size - 1.0));
+
+        holding_ends.retain(|x| *x > time);
+        value += holding_ends.len() as f64 * HOLD_BONUS;
+
+        match keys {
+            Some(keys) => {
+                for note in chord {
+                    let lane = get_lane(keys, note.x);
+                    if let Some(last) = lane_last_time[lane] {
+                        let interval = ((time - last) as f64).max(1.0);
+                        value += JACK_BONUS * (JACK_FULL_MS / interval).min(1.0);
+                    }
+                    lane_last_time[lane] = Some(time);
+                }
+            }
+            None => {
+                let x = chord.iter().map(|x| x.x as f64).sum::<f64>() / size;
+                if let Some(last_x) = last_x {
+                    let jump: f64 = (x - last_x as f64).abs() / 2.0;
+                    let speed = (JUMP_FULL_MS / dt.max(1.0)).min(1.0);
+                    value += JUMP_BONUS * jump * speed;
+                }
+                last_x = Some(x as f32);
+            }
+        }
+
+        holding_ends.extend(chord.iter().filter_map(|x| x.end_time));
+        strain += value;
+        last_time = Some(time);
+
+        let section = (time.max(0) / SECTION_MS) as usize;
+        strains[section] = strains[section].max(strain);
+    }
+
+    let mut sorted = strains.clone();
+    sorted.sort_by(|a, b| b.total_cmp(a));
+    let mut weight = 1.0;
+    let mut total = 0.0;
+    for x in sorted {
+        total += x * weight;
+        weight *= SECTION_WEIGHT_DECAY;
+    }
+
+    DifficultyRating {
+        rating: (total.sqrt() * RATING_SCALE) as f32,
+        strains: strains.into_iter().map(|x| x as f32).collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::beatmap::difficulty::calculate_difficulty;
+    use crate::game::beatmap::file::SongBeatmapFile;
+    use crate::game::beatmap::{FOUR_KEY_X, MapRule};
+    use crate::game::note::{LongNote, NormalNote, NoteHitType};
+
+    fn note(x: f32, time: i64) -> NormalNote {
+        NormalNote {
+            x,
+            width: 0.25,
+            time,
+            note_type: NoteHitType::Click,
+            timing_group: 0,
+        }
+    }
+
+    fn four_key(notes: impl IntoIterator<Item = (usize, i64)>) -> SongBeatmapFile {
+        let mut beatmap = SongBeatmapFile::new("Song".into());
+        beatmap.rule = MapRule::FourKey;
+        beatmap.normal_notes = notes
+            .into_iter()
+            .map(|(lane, time)| note(FOUR_KEY_X[lane], time))
+            .collect();
+        beatmap
+    }
+
+    #[test]
+    fn test_difficulty() {
+        let empty = calculate_difficulty(&SongBeatmapFile::new("Song".into()));
+        assert_eq!(empty.rating, 0.0);
+        assert!(empty.strains.is_empty());
+
+        let slow = calculate_difficulty(&four_key((0..20).map(|i| (i % 4, i as i64 * 500))));
+        let fast = calculate_difficulty(&four_key((0..80).map(|i| (i % 4, i as i64 * 125))));
+        assert_eq!(slow.strains.len(), 10);
+        assert_eq!(fast.strains.len(), 10);
+        assert!(fast.rating > slow.rating);
+
+        let chords = calculate_difficulty(&four_key(
+            (0..20).flat_map(|i| [(i % 4, i as i64 * 500), ((i + 2) % 4, i as i64 * 500)]),
+        ));
+        assert!(chords.rating > slow.rating);
+
+        let jacks = calculate_difficulty(&four_key((0..80).map(|i| (0, i as i64 * 125))));
+        assert!(jacks.rating > fast.rating);
+
+        let mut holds = four_key((0..20).map(|i| (i % 4, i as i64 * 500)));
+        holds.long_notes.push(LongNote {
+            x: FOUR_KEY_X[3],
+            width: 0.25,
+            start_time: 0,
+            end_time: 10000,
+            timing_group: 0,
+        });
+        assert!(calculate_difficulty(&holds).rating > slow.rating);
+
+        let mut falling = SongBeatmapFile::new("Song".into());
+        falling.normal_notes = (0..20).map(|i| note(0.0, i * 200)).collect();
+        let still = calculate_difficulty(&falling);
+        falling.normal_notes = (0..20)
+            .map(|i| note(if i % 2 == 0 { -0.8 } else { 0.8 }, i * 200))
+            .collect();
+        let jumps = calculate_difficulty(&falling);
+        assert!(jumps.rating > still.rating);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::game::beatmap::migration::load_beatmap;
 use crate::game::beatmap::MapRule;
 use crate::game::note::{LongNote, NormalNote};
 use crate::game::timing::{get_ron_options, get_ron_options_for_implicit_some, TimingGroup};
@@ -7,6 +8,9 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::io;
 use std::path::Path;
 
+/// The beatmap file version written by this game.
+pub const BEATMAP_FILE_VERSION: u8 = 4;
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct BeatmapMetadata {
     pub title: String,
@@ -25,6 +29,10 @@ impl BeatmapMetadata {
             ..Default::default()
         }
     }
+
+    pub fn get_show_name(&self) -> String {
+        format!("{}[{}]", self.title, self.version)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,32 +46,55 @@ pub struct SongBeatmapFile {
     pub long_notes: Vec<LongNote>,
     #[serde(default)]
     pub rule: MapRule,
+    /// The overall judge level in 0 to 10, the default level if not set.
+    /// See [`crate::game::beatmap::play::JudgeTimes::from_level`].
+    #[serde(default)]
+    pub judge_level: Option<f32>,
+    /// The health drain level in 0 to 10, the default level if not set.
+    /// See [`crate::game::beatmap::health::HealthRates::from_level`].
+    #[serde(default)]
+    pub drain_level: Option<f32>,
 }
 
 impl SongBeatmapFile {
-    pub(crate) fn get_show_name(&self) -> String {
-        format!("{}[{}]", self.metadata.title, self.metadata.version)
+    pub fn get_show_name(&self) -> String {
+        self.metadata.get_show_name()
     }
 
+    /// Load the beatmap file of any supported version, see [`load_beatmap`].
+    pub fn load_from_bytes(data: &[u8]) -> anyhow::Result<Self> {
+        load_beatmap(data)
+    }
+
+    /// Save the beatmap, always in the current file version.
     pub fn save_to(&self, path: &Path) -> anyhow::Result<()> {
-        let file = std::fs::File::options()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path)?;
-        ser_to_ron(&self, file, Some(PrettyConfig::default()))?;
+        std::fs::write(path, self.to_pretty_ron()?)?;
 
         Ok(())
     }
 
+    /// Serialize into the canonical pretty ron that saved in the file.
+    pub fn to_pretty_ron(&self) -> anyhow::Result<String> {
+        if self.version != BEATMAP_FILE_VERSION {
+            let mut this = self.clone();
+            this.version = BEATMAP_FILE_VERSION;
+            return this.to_pretty_ron();
+        }
+        let mut data = vec![];
+        ser_to_ron(&self, &mut data, Some(PrettyConfig::default()))?;
+        Ok(String::from_utf8(data)?)
+    }
+
     pub fn new(title: String) -> Self {
         Self {
-            version: 0,
+            version: BEATMAP_FILE_VERSION,
             metadata: BeatmapMetadata::new(title),
             timing_group: TimingGroup::new(),
             normal_notes: vec![],
             long_notes: vec![],
             rule: MapRule::Falling,
+            judge_level: None,
+            drain_level: None,
         }
     }
     
@@ -0,0 +1,142 @@
+//! The health of a play, gained by the good judgements and drained by the bad and the miss.
+
+use crate::game::beatmap::play::NoteResult;
+use crate::game::OffsetType;
+
+pub const DEFAULT_DRAIN_LEVEL: f32 = 5.0;
+pub const MAX_HEALTH: f32 = 1.0;
+
+/// The health changes of the results.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HealthRates {
+    pub perfect: f32,
+    pub great: f32,
+    pub good: f32,
+    pub bad: f32,
+    pub miss: f32,
+}
+
+impl HealthRates {
+    /// The rates of the drain level in 0 to 10, the higher the more the bad and the miss drain
+    /// and the less the good judgements gain.
+    pub fn from_level(level: f32) -> Self {
+        let base = Self::default();
+        let level = level.clamp(0.0, 10.0) - DEFAULT_DRAIN_LEVEL;
+        let gain = 1.0 - level * 0.1;
+        let drain = 1.0 + level * 0.15;
+        Self {
+            perfect: base.perfect * gain,
+            great: base.great * gain,
+            good: base.good * gain,
+            bad: base.bad * drain,
+            miss: base.miss * drain,
+        }
+    }
+
+    pub fn get_change(&self, grade: NoteResult) -> f32 {
+        match grade {
+            NoteResult::Perfect => self.perfect,
+            NoteResult::Great => self.great,
+            NoteResult::Good => self.good,
+            NoteResult::Bad => self.bad,
+            NoteResult::Miss => self.miss,
+        }
+    }
+}
+
+impl Default for HealthRates {
+    fn default() -> Self {
+        Self {
+            perfect: 0.02,
+            great: 0.01,
+            good: 0.0,
+            bad: -0.04,
+            miss: -0.08,
+        }
+    }
+}
+
+pub struct HealthCounter {
+    rates: HealthRates,
+    health: f32,
+    /// Keep playing at zero health.
+    no_fail: bool,
+    /// The time the health ran out.
+    failed_time: Option<OffsetType>,
+    /// The health after every judgement with the time, starts with the full health at 0.
+    history: Vec<(OffsetType, f32)>,
+}
+
+impl HealthCounter {
+    pub fn new(rates: HealthRates, no_fail: bool) -> Self {
+        Self {
+            rates,
+            health: MAX_HEALTH,
+            no_fail,
+            failed_time: None,
+            history: vec![(0, MAX_HEALTH)],
+        }
+    }
+
+    /// Change the health by the result judged at the `time`, nothing changes after failed.
+    pub fn accept_result(&mut self, grade: NoteResult, time: OffsetType) {
+        if self.failed_time.is_some() {
+            return;
+        }
+        self.health = (self.health + self.rates.get_change(grade)).clamp(0.0, MAX_HEALTH);
+        self.history.push((time, self.health));
+        if self.health <= 0.0 && !self.no_fail {
+            self.failed_time = Some(time);
+        }
+    }
+
+    pub fn get_health(&self) -> f32 {
+        self.health
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.failed_time.is_some()
+    }
+
+    pub fn get_failed_time(&self) -> Option<OffsetType> {
+        self.failed_time
+    }
+
+    pub fn get_history(&self) -> &[(OffsetType, f32)] {
+        &self.history
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::beatmap::health::{HealthCounter, HealthRates, DEFAULT_DRAIN_LEVEL, MAX_HEALTH};
+    use crate::game::beatmap::play::NoteResult;
+
+    #[test]
+    fn test_health() {
+        assert_eq!(HealthRates::from_level(DEFAULT_DRAIN_LEVEL), HealthRates::default());
+        let hard = HealthRates::from_level(10.0);
+        assert!(hard.miss < HealthRates::default().miss && hard.perfect < HealthRates::default().perfect);
+
+        let mut health = HealthCounter::new(HealthRates::default(), false);
+        health.accept_result(NoteResult::Perfect, 100);
+        assert_eq!(health.get_health(), MAX_HEALTH);
+        for time in 1..=13 {
+            health.accept_result(NoteResult::Miss, time * 100);
+        }
+        assert!(health.is_failed());
+        assert_eq!(health.get_failed_time(), Some(1300));
+        assert_eq!(health.get_health(), 0.0);
+        // no more changes after failed.
+        health.accept_result(NoteResult::Perfect, 1400);
+        assert_eq!(health.get_history().len(), 15);
+
+        let mut health = HealthCounter::new(HealthRates::default(), true);
+        for time in 1..=20 {
+            health.accept_result(NoteResult::Miss, time * 100);
+        }
+        assert!(!health.is_failed());
+        health.accept_result(NoteResult::Perfect, 2100);
+        assert!(health.get_health() > 0.0);
+    }
+}
@@ -0,0 +1,250 @@
+//! Check the beatmap for the problems that cannot be seen easily in the editor.
+
+use crate::game::OffsetType;
+use crate::game::beatmap::file::SongBeatmapFile;
+use crate::game::note::Note;
+use std::fmt::{Display, Formatter};
+
+/// The x range of the playfield.
+pub const PLAYFIELD_X_RANGE: (f32, f32) = (-1.0, 1.0);
+/// The notes whose x differ less than it are at the same x.
+const SAME_X_EPSILON: f32 = 1e-4;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Severity {
+    /// The beatmap can be played but may be not as expected.
+    Warning,
+    /// The beatmap is broken.
+    Error,
+}
+
+/// Which note list the index points to.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum NoteKind {
+    Normal,
+    Long,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LintKind {
+    /// Same x and same time with another note.
+    Overlap {
+        other_kind: NoteKind,
+        other_index: usize,
+    },
+    /// `x ± width / 2` is outside the playfield.
+    OutOfPlayfield,
+    /// The long note ends not after it starts.
+    InvalidLongNote,
+    /// The timing group of the note has no timing line.
+    MissingTimingLine,
+    /// The note is before 0 or after the audio end.
+    OutOfAudio,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: LintKind,
+    pub note_kind: NoteKind,
+    /// The note index in the beatmap note list of `note_kind`.
+    pub note_index: usize,
+    /// The note (start) time.
+    pub time: OffsetType,
+}
+
+impl LintKind {
+    pub fn get_severity(&self) -> Severity {
+        match self {
+            LintKind::Overlap { .. } => Severity::Error,
+            LintKind::OutOfPlayfield => Severity::Warning,
+            LintKind::InvalidLongNote => Severity::Error,
+            LintKind::MissingTimingLine => Severity::Error,
+            LintKind::OutOfAudio => Severity::Warning,
+        }
+    }
+}
+
+impl Display for NoteKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NoteKind::Normal => write!(f, "note"),
+            NoteKind::Long => write!(f, "long note"),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{:?}] {} #{} at {}ms: ",
+            self.severity, self.note_kind, self.note_index, self.time
+        )?;
+        match self.kind {
+            LintKind::Overlap {
+                other_kind,
+                other_index,
+            } => write!(f, "overlaps {} #{}", other_kind, other_index),
+            LintKind::OutOfPlayfield => write!(f, "is outside the playfield"),
+            LintKind::InvalidLongNote => write!(f, "does not end after it starts"),
+            LintKind::MissingTimingLine => write!(f, "uses a timing group without timing line"),
+            LintKind::OutOfAudio => write!(f, "is outside the audio"),
+        }
+    }
+}
+
+/// Check the beatmap, the `audio_duration` in ms is used to check notes outside the audio.
+///
+/// The diagnostics are sorted by time.
+pub fn lint_beatmap(
+    beatmap: &SongBeatmapFile,
+    audio_duration: Option<OffsetType>,
+) -> Vec<Diagnostic> {
+    let mut result = vec![];
+    let timing_lines = beatmap.timing_group.timing_lines.len();
+
+    let notes = beatmap
+        .normal_notes
+        .iter()
+        .enumerate()
+        .map(|(idx, x)| (NoteKind::Normal, idx, x as &dyn Note))
+        .chain(
+            beatmap
+                .long_notes
+                .iter()
+                .enumerate()
+                .map(|(idx, x)| (NoteKind::Long, idx, x as &dyn Note)),
+        )
+        .collect::<Vec<_>>();
+
+    let mut push = |kind: LintKind, note_kind: NoteKind, note_index: usize, time: OffsetType| {
+        result.push(Diagnostic {
+            severity: kind.get_severity(),
+            kind,
+            note_kind,
+            note_index,
+            time,
+        });
+    };
+
+    for &(note_kind, idx, note) in &notes {
+        let time = note.get_time();
+        let ext = note.get_width() / 2.0;
+        if note.get_x() - ext < PLAYFIELD_X_RANGE.0 || note.get_x() + ext > PLAYFIELD_X_RANGE.1 {
+            push(LintKind::OutOfPlayfield, note_kind, idx, time);
+        }
+        if let Some(end_time) = note.get_end_time() {
+            if end_time <= time {
+                push(LintKind::InvalidLongNote, note_kind, idx, time);
+            }
+        }
+        if note.get_timing_group() as usize >= timing_lines {
+            push(LintKind::MissingTimingLine, note_kind, idx, time);
+        }
+        let end_time = note.get_end_time().unwrap_or(time);
+        if time < 0 || audio_duration.map(|x| end_time > x).unwrap_or(false) {
+            push(LintKind::OutOfAudio, note_kind, idx, time);
+        }
+    }
+
+    let mut sorted = notes;
+    sorted.sort_by(|a, b| {
+        a.2.get_time()
+            .cmp(&b.2.get_time())
+            .then(a.2.get_x().total_cmp(&b.2.get_x()))
+    });
+    for (i, &(note_kind, idx, note)) in sorted.iter().enumerate() {
+        // report the later one only.
+        if let Some(&(other_kind, other_index, _)) = sorted[..i]
+            .iter()
+            .rev()
+            .take_while(|x| x.2.get_time() == note.get_time())
+            .find(|x| (x.2.get_x() - note.get_x()).abs() < SAME_X_EPSILON)
+        {
+            push(
+                LintKind::Overlap {
+                    other_kind,
+                    other_index,
+                },
+                note_kind,
+                idx,
+                note.get_time(),
+            );
+        }
+    }
+
+    result.sort_by_key(|x| x.time);
+    result
+}
+
+/// Is any diagnostic an error.
+pub fn has_error(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|x| x.severity == Severity::Error)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::beatmap::file::SongBeatmapFile;
+    use crate::game::beatmap::lint::{LintKind, NoteKind, has_error, lint_beatmap};
+    use crate::game::note::{LongNote, NormalNote, NoteHitType};
+
+    fn note(x: f32, time: i64) -> NormalNote {
+        NormalNote {
+            x,
+            width: 0.25,
+            time,
+            note_type: NoteHitType::Click,
+            timing_group: 0,
+        }
+    }
+
+    #[test]
+    fn test_lint() {
+        let mut beatmap = SongBeatmapFile::new("Song".into());
+        beatmap.normal_notes = vec![note(0.0, 100), note(0.5, 200)];
+        beatmap.long_notes = vec![LongNote {
+            x: -0.25,
+            width: 0.25,
+            start_time: 300,
+            end_time: 500,
+            timing_group: 0,
+        }];
+        assert!(lint_beatmap(&beatmap, Some(1000)).is_empty());
+
+        beatmap.normal_notes.push(note(0.0, 100));
+        beatmap.normal_notes.push(note(0.95, 400));
+        beatmap.normal_notes.push(NormalNote {
+            timing_group: 3,
+            ..note(-0.5, 600)
+        });
+        beatmap.long_notes[0].end_time = 300;
+        beatmap.normal_notes.push(note(0.25, 2000));
+
+        let result = lint_beatmap(&beatmap, Some(1000));
+        let kinds = result
+            .iter()
+            .map(|x| (x.kind, x.note_kind, x.note_index, x.time))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                (
+                    LintKind::Overlap {
+                        other_kind: NoteKind::Normal,
+                        other_index: 0
+                    },
+                    NoteKind::Normal,
+                    2,
+                    100
+                ),
+                (LintKind::InvalidLongNote, NoteKind::Long, 0, 300),
+                (LintKind::OutOfPlayfield, NoteKind::Normal, 3, 400),
+                (LintKind::MissingTimingLine, NoteKind::Normal, 4, 600),
+                (LintKind::OutOfAudio, NoteKind::Normal, 5, 2000),
+            ]
+        );
+        assert!(has_error(&result));
+        assert!(!has_error(&result[2..3]));
+    }
+}
@@ -0,0 +1,76 @@
+//! Load the beatmap file of every version and upgrade it to the current [`SongBeatmapFile`].
+//!
+//! Every file version has a loader in [`LOADERS`] indexed by the version, bump
+//! [`BEATMAP_FILE_VERSION`] whenever the file changes.
+//!
+//! The fields added so far are all `serde(default)`, so the old loaders read the current
+//! [`SongBeatmapFile`] and reset what the old version did not have, then fall through to the next
+//! loader. A change the current struct cannot read, e.g. a field renamed, removed or retyped,
+//! needs a struct of the old layout here converted to the current one.
+
+use crate::game::beatmap::file::{de_from_ron, SongBeatmapFile, BEATMAP_FILE_VERSION};
+use crate::game::timing::get_ron_options;
+use anyhow::anyhow;
+use serde::Deserialize;
+
+type Loader = fn(&[u8]) -> anyhow::Result<SongBeatmapFile>;
+
+/// The loaders index by the file version.
+const LOADERS: [Loader; BEATMAP_FILE_VERSION as usize + 1] = [load_v0, load_v1, load_v2, load_v3, load_v4];
+
+/// Only read the version to select the loader.
+#[derive(Deserialize)]
+#[serde(rename = "SongBeatmapFile")]
+struct VersionProbe {
+    #[serde(default)]
+    version: u8,
+}
+
+/// Get the file version of the beatmap data.
+pub fn get_file_version(data: &[u8]) -> anyhow::Result<u8> {
+    Ok(de_from_ron::<VersionProbe>(data)?.version)
+}
+
+/// Load the beatmap of any supported version and upgrade it to the current version.
+pub fn load_beatmap(data: &[u8]) -> anyhow::Result<SongBeatmapFile> {
+    let version = get_file_version(data)?;
+    let loader = LOADERS.get(version as usize).ok_or(anyhow!(
+        "The beatmap version {} is newer than the supported version {}, please update the game",
+        version,
+        BEATMAP_FILE_VERSION
+    ))?;
+    let mut beatmap = loader(data)?;
+    beatmap.version = BEATMAP_FILE_VERSION;
+    Ok(beatmap)
+}
+
+/// The version 0 files were written by the early editor with different ron options.
+fn load_v0(data: &[u8]) -> anyhow::Result<SongBeatmapFile> {
+    de_from_ron(data)
+}
+
+/// The version 1 files have no judge level, the default windows were always used.
+fn load_v1(data: &[u8]) -> anyhow::Result<SongBeatmapFile> {
+    let mut beatmap = load_v2(data)?;
+    beatmap.judge_level = None;
+    Ok(beatmap)
+}
+
+/// The version 2 files have no drain level, the default health rates were always used.
+fn load_v2(data: &[u8]) -> anyhow::Result<SongBeatmapFile> {
+    let mut beatmap = load_v3(data)?;
+    beatmap.drain_level = None;
+    Ok(beatmap)
+}
+
+/// The version 3 files have no key count rule other than 4K, they load as the current version.
+fn load_v3(data: &[u8]) -> anyhow::Result<SongBeatmapFile> {
+    load_v4(data)
+}
+
+fn load_v4(data: &[u8]) -> anyhow::Result<SongBeatmapFile> {
+    let mut der = ron::Deserializer::from_bytes_with_options(data, get_ron_options())?;
+    let beatmap = SongBeatmapFile::deserialize(&mut der)?;
+    der.end()?;
+    Ok(beatmap)
+}
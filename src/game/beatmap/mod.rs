@@ -1,11 +1,21 @@
 //! The real playing beatmap that contains detail notes.
 
+pub mod difficulty;
 pub mod file;
+pub mod health;
+pub mod lint;
+pub mod migration;
+pub mod mods;
+pub mod osu;
 pub mod play;
-mod test;
+pub mod sm;
+pub mod stats;
+pub(crate) mod test;
 pub mod summary;
 
-use crate::game::beatmap::file::SongBeatmapFile;
+use crate::game::beatmap::difficulty::{calculate_difficulty, DifficultyRating};
+use crate::game::beatmap::file::{BeatmapMetadata, SongBeatmapFile};
+use crate::game::beatmap::stats::BeatmapStats;
 use crate::game::OffsetType;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
@@ -13,18 +23,132 @@ use std::path::PathBuf;
 
 pub static FOUR_KEY_X: [f32; 4] = [-0.75, -0.25, 0.25, 0.75];
 
+/// The key counts of the lane rules.
+pub const MIN_KEYS: u8 = 4;
+pub const MAX_KEYS: u8 = 10;
+
+/// The key count out of range is rejected when loaded, `Keys(4)` is loaded as [`MapRule::FourKey`].
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(try_from = "RawMapRule")]
 pub enum MapRule {
     Falling,
     FourKey,
+    /// The lanes other than 4K, in [`MIN_KEYS`] to [`MAX_KEYS`].
+    Keys(u8),
+}
+
+/// The [`MapRule`] before the key count is checked.
+#[derive(Deserialize)]
+enum RawMapRule {
+    Falling,
+    FourKey,
+    Keys(u8),
+}
+
+impl TryFrom<RawMapRule> for MapRule {
+    type Error = String;
+
+    fn try_from(value: RawMapRule) -> Result<Self, Self::Error> {
+        match value {
+            RawMapRule::Falling => Ok(Self::Falling),
+            RawMapRule::FourKey => Ok(Self::FourKey),
+            RawMapRule::Keys(keys) if (MIN_KEYS..=MAX_KEYS).contains(&keys) => {
+                Ok(Self::from_keys(keys))
+            }
+            RawMapRule::Keys(keys) => Err(format!(
+                "The key count {} is not in {} to {}",
+                keys, MIN_KEYS, MAX_KEYS
+            )),
+        }
+    }
+}
+
+impl MapRule {
+    /// The lane rule of the key count, 4K is [`MapRule::FourKey`].
+    pub fn from_keys(keys: u8) -> Self {
+        match keys {
+            4 => Self::FourKey,
+            _ => Self::Keys(keys.clamp(MIN_KEYS, MAX_KEYS)),
+        }
+    }
+
+    /// The key count of the lane rule, none for falling.
+    pub fn get_keys(&self) -> Option<u8> {
+        match self {
+            MapRule::Falling => None,
+            MapRule::FourKey => Some(4),
+            MapRule::Keys(keys) => Some(*keys),
+        }
+    }
+
+    /// Falling and the key lanes from [`MIN_KEYS`] to [`MAX_KEYS`].
+    pub fn all() -> impl Iterator<Item = MapRule> {
+        std::iter::once(MapRule::Falling).chain((MIN_KEYS..=MAX_KEYS).map(MapRule::from_keys))
+    }
+
+    pub fn get_name(&self) -> String {
+        match self.get_keys() {
+            Some(keys) => format!("{}K", keys),
+            None => "Falling".into(),
+        }
+    }
+}
+
+/// The x of the lanes, the lanes split the play area from -1 to 1 equally.
+pub fn get_lanes_x(keys: u8) -> Vec<f32> {
+    let lane_width = 2.0 / keys as f32;
+    (0..keys)
+        .map(|lane| -1.0 + lane_width * (lane as f32 + 0.5))
+        .collect()
+}
+
+/// The width of the note in the lane.
+pub fn get_lane_note_width(keys: u8) -> f32 {
+    1.0 / keys as f32
+}
+
+/// The nearest lane of the x.
+pub fn get_lane(keys: u8, x: f32) -> usize {
+    let lane = ((x + 1.0) * keys as f32 / 2.0).floor();
+    (lane.max(0.0) as usize).min(keys as usize - 1)
 }
 
 pub const BEATMAP_EXT: &'static str = "rr";
 
-#[derive(Clone, Debug)]
+/// The beatmap summary shown in the song list, the notes are loaded by [`Self::load_beatmap`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SongBeatmapInfo {
     pub file_path: PathBuf,
-    pub song_beatmap_file: SongBeatmapFile,
+    pub metadata: BeatmapMetadata,
+    pub rule: MapRule,
+    pub difficulty: DifficultyRating,
+    pub stats: BeatmapStats,
+}
+
+impl SongBeatmapInfo {
+    pub fn new(file_path: PathBuf, beatmap: &SongBeatmapFile) -> Self {
+        let difficulty = calculate_difficulty(beatmap);
+        Self {
+            file_path,
+            metadata: beatmap.metadata.clone(),
+            rule: beatmap.rule,
+            stats: BeatmapStats::with_rating(beatmap, difficulty.rating),
+            difficulty,
+        }
+    }
+
+    /// Parse the beatmap file for the summary.
+    pub fn load(file_path: PathBuf) -> anyhow::Result<Self> {
+        let beatmap = SongBeatmapFile::load_from_bytes(&std::fs::read(&file_path)?)?;
+        Ok(Self::new(file_path, &beatmap))
+    }
+
+    /// Load the whole beatmap to play or edit.
+    pub fn load_beatmap(&self) -> anyhow::Result<SongBeatmapFile> {
+        let mut beatmap = SongBeatmapFile::load_from_bytes(&std::fs::read(&self.file_path)?)?;
+        beatmap.update();
+        Ok(beatmap)
+    }
 }
 
 impl Default for MapRule {
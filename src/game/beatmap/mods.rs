@@ -0,0 +1,207 @@
+//! The play modifiers, applied when the [`crate::game::beatmap::play::Gaming`] is loaded.
+
+use crate::game::beatmap::file::SongBeatmapFile;
+use crate::game::beatmap::{get_lane, get_lanes_x};
+use serde::{Deserialize, Serialize};
+
+pub const MIN_RATE: f32 = 0.5;
+pub const MAX_RATE: f32 = 2.0;
+pub const MIN_SCROLL_SPEED: f32 = 0.25;
+pub const MAX_SCROLL_SPEED: f32 = 4.0;
+
+/// The y ranges covered by hidden and sudden, in the play area from the judgement line (0) to the
+/// top (1). The notes are covered from the edge to the first y, and fade in to the second y.
+pub const HIDDEN_COVER: (f32, f32) = (0.2, 0.45);
+pub const SUDDEN_COVER: (f32, f32) = (0.75, 0.5);
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayMods {
+    /// Flip the notes horizontally.
+    pub mirror: bool,
+    /// Shuffle the key lanes with the seed.
+    pub random: Option<u64>,
+    /// Hide the notes near the judgement line.
+    pub hidden: bool,
+    /// Show the notes near the judgement line only.
+    pub sudden: bool,
+    /// Keep playing when the health runs out.
+    pub no_fail: bool,
+    /// Hit every note perfectly.
+    pub auto_play: bool,
+    /// The multiplier of the note speed, in [`MIN_SCROLL_SPEED`] to [`MAX_SCROLL_SPEED`].
+    pub scroll_speed: f32,
+    /// The audio playback rate, in [`MIN_RATE`] to [`MAX_RATE`].
+    /// The judgement windows are scaled with it to keep the same length in the real time.
+    pub rate: f32,
+}
+
+impl Default for PlayMods {
+    fn default() -> Self {
+        Self {
+            mirror: false,
+            random: None,
+            hidden: false,
+            sudden: false,
+            no_fail: false,
+            auto_play: false,
+            scroll_speed: 1.0,
+            rate: 1.0,
+        }
+    }
+}
+
+impl PlayMods {
+    /// The score multiplier of the mods.
+    pub fn get_multiplier(&self) -> f32 {
+        if self.auto_play {
+            return 0.0;
+        }
+        let mut multiplier = 1.0;
+        if self.hidden {
+            multiplier *= 1.06;
+        }
+        if self.sudden {
+            multiplier *= 1.06;
+        }
+        if self.no_fail {
+            multiplier *= 0.5;
+        }
+        let rate = self.get_rate();
+        if rate < 1.0 {
+            multiplier *= rate;
+        } else {
+            multiplier *= 1.0 + (rate - 1.0) * 0.2;
+        }
+        multiplier
+    }
+
+    pub fn get_rate(&self) -> f32 {
+        self.rate.clamp(MIN_RATE, MAX_RATE)
+    }
+
+    pub fn get_scroll_speed(&self) -> f32 {
+        self.scroll_speed.clamp(MIN_SCROLL_SPEED, MAX_SCROLL_SPEED)
+    }
+
+    /// The names of the active mods to show.
+    pub fn get_names(&self) -> Vec<String> {
+        let mut names = vec![];
+        for (active, name) in [
+            (self.auto_play, "Auto"),
+            (self.mirror, "Mirror"),
+            (self.random.is_some(), "Random"),
+            (self.hidden, "Hidden"),
+            (self.sudden, "Sudden"),
+            (self.no_fail, "NoFail"),
+        ] {
+            if active {
+                names.push(name.to_string());
+            }
+        }
+        if self.get_rate() != 1.0 {
+            names.push(format!("{:.2}x", self.get_rate()));
+        }
+        if self.get_scroll_speed() != 1.0 {
+            names.push(format!("Speed {:.2}", self.get_scroll_speed()));
+        }
+        names
+    }
+
+    /// Move the notes for the mirror and the random.
+    pub fn apply(&self, beatmap: &mut SongBeatmapFile) {
+        let mut map_x: Box<dyn Fn(f32) -> f32> = Box::new(|x| x);
+        if let (Some(seed), Some(keys)) = (self.random, beatmap.rule.get_keys()) {
+            let lanes_x = get_lanes_x(keys);
+            let lanes = shuffle_lanes(keys, seed);
+            // keep the offset in the lane.
+            map_x = Box::new(move |x| {
+                let lane = get_lane(keys, x);
+                x - lanes_x[lane] + lanes_x[lanes[lane]]
+            });
+        }
+        if self.mirror {
+            map_x = Box::new(move |x| -map_x(x));
+        }
+        for note in &mut beatmap.normal_notes {
+            note.x = map_x(note.x);
+        }
+        for note in &mut beatmap.long_notes {
+            note.x = map_x(note.x);
+        }
+    }
+}
+
+/// The shuffled lane of every lane, the same seed gets the same lanes.
+pub fn shuffle_lanes(keys: u8, seed: u64) -> Vec<usize> {
+    let mut lanes = (0..keys as usize).collect::<Vec<_>>();
+    // xorshift64*, the seed should not be 0.
+    let mut state = seed | 1;
+    let mut next = || {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    };
+    for i in (1..lanes.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        lanes.swap(i, j);
+    }
+    lanes
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::beatmap::file::SongBeatmapFile;
+    use crate::game::beatmap::mods::{shuffle_lanes, PlayMods};
+    use crate::game::beatmap::{get_lanes_x, MapRule, FOUR_KEY_X};
+    use crate::game::note::{NormalNote, NoteHitType};
+
+    #[test]
+    fn test_mods() {
+        let mut beatmap = SongBeatmapFile::new("Song".into());
+        beatmap.rule = MapRule::Keys(7);
+        let lanes_x = get_lanes_x(7);
+        beatmap.normal_notes = lanes_x
+            .iter()
+            .map(|x| NormalNote {
+                x: *x,
+                width: 1.0 / 7.0,
+                time: 0,
+                note_type: NoteHitType::Click,
+                timing_group: 0,
+            })
+            .collect();
+
+        let mut mirrored = beatmap.clone();
+        PlayMods { mirror: true, ..Default::default() }.apply(&mut mirrored);
+        assert_eq!(mirrored.normal_notes[0].x, -lanes_x[0]);
+
+        let lanes = shuffle_lanes(7, 42);
+        assert_eq!(lanes, shuffle_lanes(7, 42));
+        let mut sorted = lanes.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..7).collect::<Vec<_>>());
+
+        let mut random = beatmap.clone();
+        PlayMods { random: Some(42), ..Default::default() }.apply(&mut random);
+        for (idx, note) in random.normal_notes.iter().enumerate() {
+            assert!((note.x - lanes_x[lanes[idx]]).abs() < 1e-6);
+        }
+
+        // the falling beatmap is not shuffled.
+        let mut falling = SongBeatmapFile::new("Song".into());
+        falling.normal_notes = beatmap.normal_notes.clone();
+        PlayMods { random: Some(42), ..Default::default() }.apply(&mut falling);
+        assert_eq!(falling.normal_notes, beatmap.normal_notes);
+
+        assert_eq!(PlayMods::default().get_multiplier(), 1.0);
+        assert!(PlayMods { hidden: true, ..Default::default() }.get_multiplier() > 1.0);
+        assert!(PlayMods { rate: 0.5, ..Default::default() }.get_multiplier() < 1.0);
+        assert_eq!(PlayMods { auto_play: true, ..Default::default() }.get_multiplier(), 0.0);
+        assert!(PlayMods::default().get_names().is_empty());
+        let names = PlayMods { mirror: true, rate: 1.5, ..Default::default() }.get_names();
+        assert_eq!(names, ["Mirror", "1.50x"]);
+        assert_eq!(FOUR_KEY_X.len(), shuffle_lanes(4, 1).len());
+    }
+}
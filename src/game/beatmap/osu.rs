@@ -0,0 +1,584 @@
+//! Convert osu!mania `.osu` charts from and into [`SongBeatmapFile`].
+//!
+//! Only the sections we need are read: `General`, `Metadata`, `Difficulty`, `Events`,
+//! `TimingPoints` and `HitObjects`.
+
+use crate::game::OffsetType;
+use crate::game::beatmap::file::SongBeatmapFile;
+use crate::game::beatmap::health::DEFAULT_DRAIN_LEVEL;
+use crate::game::beatmap::play::DEFAULT_JUDGE_LEVEL;
+use crate::game::beatmap::{get_lane, get_lane_note_width, get_lanes_x, MapRule, MAX_KEYS, MIN_KEYS};
+use crate::game::note::{LongNote, NormalNote, NoteHitType};
+use crate::game::timing::{Bpm, Timing};
+use anyhow::anyhow;
+use std::fmt::Write;
+use std::num::NonZeroU8;
+
+pub const OSU_EXT: &'static str = "osu";
+
+/// The mania playfield width in osu! pixels.
+const OSU_PLAYFIELD_WIDTH: f32 = 512.0;
+/// The osu! game mode id of mania.
+const OSU_MANIA_MODE: u8 = 3;
+/// osu! hit object type flag of hit circle.
+const OSU_CIRCLE_FLAG: u32 = 1;
+/// osu! hit object type flag of hold note.
+const OSU_HOLD_FLAG: u32 = 1 << 7;
+/// The speed range osu! supports.
+const OSU_SPEED_RANGE: (f32, f32) = (0.01, 10.0);
+
+pub struct OsuBeatmap {
+    /// The audio file name relative to the `.osu` file.
+    pub audio_filename: String,
+    /// The preview point in ms, if the chart set.
+    pub preview_time: Option<OffsetType>,
+    /// The background image file name relative to the `.osu` file, if the chart set.
+    pub background: Option<String>,
+    pub beatmap: SongBeatmapFile,
+}
+
+struct OsuTimingPoint {
+    time: f64,
+    beat_length: f64,
+    meter: u8,
+    uninherited: bool,
+}
+
+fn parse_key_value(line: &str) -> Option<(&str, &str)> {
+    line.split_once(':').map(|(k, v)| (k.trim(), v.trim()))
+}
+
+fn parse_timing_point(line: &str) -> anyhow::Result<OsuTimingPoint> {
+    let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+    if fields.len() < 2 {
+        return Err(anyhow!("Invalid timing point {}", line));
+    }
+    let time = fields[0].parse::<f64>()?;
+    let beat_length = fields[1].parse::<f64>()?;
+    let meter = fields
+        .get(2)
+        .and_then(|x| x.parse::<u8>().ok())
+        .unwrap_or(4);
+    // old format has no uninherited field, the negative beat length means inherited.
+    let uninherited = fields
+        .get(6)
+        .map(|x| *x == "1")
+        .unwrap_or(beat_length > 0.0);
+    Ok(OsuTimingPoint {
+        time,
+        beat_length,
+        meter,
+        uninherited,
+    })
+}
+
+fn convert_timings(points: &mut Vec<OsuTimingPoint>) -> anyhow::Result<Vec<Timing>> {
+    // uninherited first if at the same time, so the speed can be merged into it.
+    points.sort_by(|a, b| {
+        a.time
+            .total_cmp(&b.time)
+            .then(b.uninherited.cmp(&a.uninherited))
+    });
+
+    let mut timings: Vec<Timing> = vec![];
+    let mut time_signature = NonZeroU8::new(4).unwrap();
+    for point in points.iter() {
+        if point.uninherited {
+            if point.beat_length <= 0.0 || !point.beat_length.is_finite() {
+                return Err(anyhow!("Invalid beat length {}", point.beat_length));
+            }
+            time_signature = NonZeroU8::new(point.meter).unwrap_or(time_signature);
+            // our timing cannot start before 0, move it by whole beats to keep the beat grid.
+            let mut time = point.time;
+            if time < 0.0 {
+                time += (-time / point.beat_length).ceil() * point.beat_length;
+            }
+            let offset = time.round() as OffsetType;
+            let mut timing = Timing::new(
+                Bpm::from(60_000.0 / point.beat_length),
+                offset,
+                time_signature,
+            );
+            // uninherited point resets the scroll speed in osu!
+            timing.set_speed = Some(1.0);
+            timings.retain(|x| x.offset != offset);
+            timings.push(timing);
+        } else {
+            let speed =
+                ((-100.0 / point.beat_length) as f32).clamp(OSU_SPEED_RANGE.0, OSU_SPEED_RANGE.1);
+            let offset = (point.time.round() as OffsetType).max(0);
+            match timings.last_mut() {
+                Some(last) if last.offset == offset => {
+                    last.set_speed = Some(speed);
+                }
+                _ => {
+                    timings.push(Timing::new_speed(speed, offset, time_signature));
+                }
+            }
+        }
+    }
+    Ok(timings)
+}
+
+/// Parse the osu!mania chart.
+pub fn parse_osu(data: &str) -> anyhow::Result<OsuBeatmap> {
+    let mut section = "";
+    let mut audio_filename = None;
+    let mut preview_time = None;
+    let mut background = None;
+    let mut mode = 0;
+    let mut keys = None;
+    let mut beatmap = SongBeatmapFile::new(String::new());
+    let mut title_unicode = String::new();
+    let mut artist_unicode = String::new();
+    let mut points = vec![];
+    let mut objects = vec![];
+
+    for line in data.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            section = &line[1..line.len() - 1];
+            continue;
+        }
+        match section {
+            "General" => match parse_key_value(line) {
+                Some(("AudioFilename", v)) => audio_filename = Some(v.to_string()),
+                Some(("PreviewTime", v)) => {
+                    preview_time = v.parse::<OffsetType>().ok().filter(|x| *x >= 0)
+                }
+                Some(("Mode", v)) => mode = v.parse::<u8>()?,
+                _ => {}
+            },
+            "Metadata" => {
+                let metadata = &mut beatmap.metadata;
+                match parse_key_value(line) {
+                    Some(("Title", v)) => metadata.title = v.to_string(),
+                    Some(("TitleUnicode", v)) => title_unicode = v.to_string(),
+                    Some(("Artist", v)) => metadata.artist = v.to_string(),
+                    Some(("ArtistUnicode", v)) => artist_unicode = v.to_string(),
+                    Some(("Creator", v)) => metadata.creator = v.to_string(),
+                    Some(("Version", v)) => metadata.version = v.to_string(),
+                    Some(("Source", v)) => metadata.source = v.to_string(),
+                    Some(("Tags", v)) => {
+                        metadata.tags = v.split_whitespace().collect::<Vec<_>>().join(",")
+                    }
+                    _ => {}
+                }
+            }
+            "Difficulty" => match parse_key_value(line) {
+                Some(("CircleSize", v)) => keys = Some(v.parse::<f32>()?.round() as usize),
+                Some(("HPDrainRate", v)) => beatmap.drain_level = v.parse::<f32>().ok(),
+                Some(("OverallDifficulty", v)) => beatmap.judge_level = v.parse::<f32>().ok(),
+                _ => {}
+            },
+            "Events" => {
+                // 0,0,"bg.jpg",0,0
+                let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+                if background.is_none() && fields.len() >= 3 && fields[0] == "0" {
+                    background = Some(fields[2].trim_matches('"').to_string());
+                }
+            }
+            "TimingPoints" => points.push(parse_timing_point(line)?),
+            "HitObjects" => objects.push(line),
+            _ => {}
+        }
+    }
+
+    if mode != OSU_MANIA_MODE {
+        return Err(anyhow!(
+            "Only osu!mania chart is supported, got mode {}",
+            mode
+        ));
+    }
+    let keys = keys.ok_or(anyhow!("No key count (CircleSize) found"))?;
+    if !(MIN_KEYS as usize..=MAX_KEYS as usize).contains(&keys) {
+        return Err(anyhow!(
+            "Only {}K to {}K chart is supported, got {}K",
+            MIN_KEYS,
+            MAX_KEYS,
+            keys
+        ));
+    }
+    let audio_filename = audio_filename.ok_or(anyhow!("No audio file found"))?;
+    if !title_unicode.is_empty() {
+        beatmap.metadata.title = title_unicode;
+    }
+    if !artist_unicode.is_empty() {
+        beatmap.metadata.artist = artist_unicode;
+    }
+
+    beatmap.rule = MapRule::from_keys(keys as u8);
+    beatmap.timing_group.timing_lines[0].timings = convert_timings(&mut points)?;
+
+    let lanes_x = get_lanes_x(keys as u8);
+    let note_width = get_lane_note_width(keys as u8);
+    for object in objects {
+        let fields = object.split(',').map(str::trim).collect::<Vec<_>>();
+        if fields.len() < 5 {
+            return Err(anyhow!("Invalid hit object {}", object));
+        }
+        let column = ((fields[0].parse::<f32>()? * keys as f32 / OSU_PLAYFIELD_WIDTH).floor()
+            as usize)
+            .min(keys - 1);
+        let x = lanes_x[column];
+        let time = fields[2].parse::<f64>()?.round() as OffsetType;
+        let object_type = fields[3].parse::<u32>()?;
+        if object_type & OSU_HOLD_FLAG != 0 {
+            let end_time = fields
+                .get(5)
+                .and_then(|x| x.split(':').next())
+                .ok_or(anyhow!("No end time for hold {}", object))?
+                .parse::<f64>()?
+                .round() as OffsetType;
+            beatmap.long_notes.push(LongNote {
+                x,
+                width: note_width,
+                start_time: time,
+                end_time,
+                timing_group: 0,
+            });
+        } else {
+            beatmap.normal_notes.push(NormalNote {
+                x,
+                width: note_width,
+                time,
+                note_type: NoteHitType::Click,
+                timing_group: 0,
+            });
+        }
+    }
+    beatmap.update();
+
+    Ok(OsuBeatmap {
+        audio_filename,
+        preview_time,
+        background,
+        beatmap,
+    })
+}
+
+/// Export the key lanes beatmap as osu!mania chart.
+///
+/// The beatmap should be updated, the speed out of the osu! range will be clamped.
+/// osu! has no timing groups, so the beatmap using more than one is rejected.
+pub fn export_osu(
+    beatmap: &SongBeatmapFile,
+    audio_filename: &str,
+    preview_time: Option<OffsetType>,
+) -> anyhow::Result<String> {
+    let keys = beatmap
+        .rule
+        .get_keys()
+        .ok_or(anyhow!("Only key lanes beatmap can be exported to osu!mania"))?;
+    let uses_groups = beatmap.timing_group.timing_lines.len() > 1
+        || beatmap.normal_notes.iter().any(|x| x.timing_group != 0)
+        || beatmap.long_notes.iter().any(|x| x.timing_group != 0);
+    if uses_groups {
+        return Err(anyhow!("Only one timing group can be exported to osu!mania"));
+    }
+    let metadata = &beatmap.metadata;
+    let mut result = String::new();
+
+    writeln!(result, "osu file format v14")?;
+    writeln!(result)?;
+    writeln!(result, "[General]")?;
+    writeln!(result, "AudioFilename: {}", audio_filename)?;
+    writeln!(result, "AudioLeadIn: 0")?;
+    writeln!(result, "PreviewTime: {}", preview_time.unwrap_or(-1))?;
+    writeln!(result, "Mode: {}", OSU_MANIA_MODE)?;
+    writeln!(result)?;
+    writeln!(result, "[Metadata]")?;
+    writeln!(result, "Title:{}", metadata.title)?;
+    writeln!(result, "TitleUnicode:{}", metadata.title)?;
+    writeln!(result, "Artist:{}", metadata.artist)?;
+    writeln!(result, "ArtistUnicode:{}", metadata.artist)?;
+    writeln!(result, "Creator:{}", metadata.creator)?;
+    writeln!(result, "Version:{}", metadata.version)?;
+    writeln!(result, "Source:{}", metadata.source)?;
+    let tags = metadata
+        .tags
+        .split(',')
+        .map(|x| x.trim().replace(' ', "_"))
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    writeln!(result, "Tags:{}", tags.join(" "))?;
+    writeln!(result)?;
+    writeln!(result, "[Difficulty]")?;
+    writeln!(
+        result,
+        "HPDrainRate:{}",
+        beatmap.drain_level.unwrap_or(DEFAULT_DRAIN_LEVEL)
+    )?;
+    writeln!(result, "CircleSize:{}", keys)?;
+    writeln!(
+        result,
+        "OverallDifficulty:{}",
+        beatmap.judge_level.unwrap_or(DEFAULT_JUDGE_LEVEL)
+    )?;
+    writeln!(result, "ApproachRate:5")?;
+    writeln!(result, "SliderMultiplier:1.4")?;
+    writeln!(result, "SliderTickRate:1")?;
+    writeln!(result)?;
+
+    writeln!(result, "[TimingPoints]")?;
+    let timings = beatmap
+        .timing_group
+        .timing_lines
+        .first()
+        .map(|x| &x.timings[..])
+        .unwrap_or(&[]);
+    for (idx, timing) in timings.iter().enumerate() {
+        let meter = timing.time_signature.get();
+        // osu! needs the first timing point uninherited.
+        if timing.set_bpm.is_some() || idx == 0 {
+            let bpm: f32 = timing.get_bpm().into();
+            writeln!(
+                result,
+                "{},{},{},1,0,100,1,0",
+                timing.offset,
+                60_000.0 / bpm as f64,
+                meter
+            )?;
+        }
+        // uninherited point resets the speed to 1 in osu!
+        let need_speed = match timing.set_speed {
+            Some(speed) => timing.set_bpm.is_none() || speed != 1.0,
+            None => timing.get_speed() != 1.0,
+        };
+        if need_speed {
+            let speed = timing
+                .get_speed()
+                .clamp(OSU_SPEED_RANGE.0, OSU_SPEED_RANGE.1);
+            writeln!(
+                result,
+                "{},{},{},1,0,100,0,0",
+                timing.offset,
+                -100.0 / speed as f64,
+                meter
+            )?;
+        }
+    }
+    writeln!(result)?;
+
+    writeln!(result, "[HitObjects]")?;
+    // the column is the nearest lane.
+    let x_of = |x: f32| (get_lane(keys, x) as f32 + 0.5) * OSU_PLAYFIELD_WIDTH / keys as f32;
+    // (time, line)
+    let mut objects = beatmap
+        .normal_notes
+        .iter()
+        .map(|x| {
+            (
+                x.time,
+                format!(
+                    "{},192,{},{},0,0:0:0:0:",
+                    x_of(x.x).floor(),
+                    x.time,
+                    OSU_CIRCLE_FLAG
+                ),
+            )
+        })
+        .chain(beatmap.long_notes.iter().map(|x| {
+            (
+                x.start_time,
+                format!(
+                    "{},192,{},{},0,{}:0:0:0:0:",
+                    x_of(x.x).floor(),
+                    x.start_time,
+                    OSU_HOLD_FLAG,
+                    x.end_time
+                ),
+            )
+        }))
+        .collect::<Vec<_>>();
+    objects.sort_by_key(|x| x.0);
+    for (_, line) in objects {
+        writeln!(result, "{}", line)?;
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::beatmap::file::SongBeatmapFile;
+    use crate::game::beatmap::osu::{export_osu, parse_osu};
+    use crate::game::beatmap::{get_lanes_x, FOUR_KEY_X, MapRule};
+    use crate::game::note::{LongNote, NormalNote, NoteHitType};
+    use crate::game::timing::{Bpm, Timing};
+    use std::num::NonZeroU8;
+
+    const CHART: &str = "osu file format v14
+
+[General]
+AudioFilename: audio.mp3
+PreviewTime: 1000
+Mode: 3
+
+[Metadata]
+Title:Song
+TitleUnicode:歌
+Artist:Someone
+Creator:Mapper
+Version:Hard
+Tags:tag1 tag2
+
+[Difficulty]
+HPDrainRate:8
+CircleSize:4
+OverallDifficulty:7.5
+
+[Events]
+//Background and Video events
+0,0,\"bg.jpg\",0,0
+
+[TimingPoints]
+-250,500,4,2,0,100,1,0
+1000,-50,4,2,0,100,0,0
+2000,250,3,2,0,100,1,0
+2000,-200,3,2,0,100,0,0
+
+[HitObjects]
+64,192,0,1,0,0:0:0:0:
+448,192,500,128,0,1500:0:0:0:0:
+192,192,2000,5,0,0:0:0:0:
+";
+
+    #[test]
+    fn test_parse() {
+        let osu = parse_osu(CHART).unwrap();
+        assert_eq!(osu.audio_filename, "audio.mp3");
+        assert_eq!(osu.preview_time, Some(1000));
+        assert_eq!(osu.background.as_deref(), Some("bg.jpg"));
+        let beatmap = osu.beatmap;
+        assert_eq!(beatmap.rule, MapRule::FourKey);
+        assert_eq!(beatmap.metadata.title, "歌");
+        assert_eq!(beatmap.metadata.tags, "tag1,tag2");
+        assert_eq!(beatmap.judge_level, Some(7.5));
+        assert_eq!(beatmap.drain_level, Some(8.0));
+
+        let timings = &beatmap.timing_group.timing_lines[0].timings;
+        assert_eq!(timings.len(), 3);
+        // moved by one beat from -250
+        assert_eq!(timings[0].offset, 250);
+        assert_eq!(timings[0].get_bpm().to_string(), "120.00");
+        assert_eq!(timings[1].set_bpm, None);
+        assert_eq!(timings[1].set_speed, Some(2.0));
+        assert_eq!(timings[2].offset, 2000);
+        assert_eq!(timings[2].get_bpm().to_string(), "240.00");
+        assert_eq!(timings[2].set_speed, Some(0.5));
+        assert_eq!(timings[2].time_signature.get(), 3);
+
+        assert_eq!(beatmap.normal_notes.len(), 2);
+        assert_eq!(beatmap.normal_notes[0].x, FOUR_KEY_X[0]);
+        assert_eq!(beatmap.normal_notes[1].x, FOUR_KEY_X[1]);
+        assert_eq!(beatmap.long_notes.len(), 1);
+        assert_eq!(beatmap.long_notes[0].x, FOUR_KEY_X[3]);
+        assert_eq!(beatmap.long_notes[0].start_time, 500);
+        assert_eq!(beatmap.long_notes[0].end_time, 1500);
+    }
+
+    #[test]
+    fn test_reject_other_modes() {
+        assert!(parse_osu(&CHART.replace("Mode: 3", "Mode: 0")).is_err());
+        assert!(parse_osu(&CHART.replace("CircleSize:4", "CircleSize:12")).is_err());
+    }
+
+    #[test]
+    fn test_parse_seven_keys() {
+        let beatmap = parse_osu(&CHART.replace("CircleSize:4", "CircleSize:7")).unwrap().beatmap;
+        assert_eq!(beatmap.rule, MapRule::Keys(7));
+        let lanes_x = get_lanes_x(7);
+        assert!(beatmap.normal_notes.iter().all(|x| lanes_x.contains(&x.x)));
+        let exported = export_osu(&beatmap, "audio.mp3", None).unwrap();
+        assert!(exported.contains("CircleSize:7"));
+        assert_eq!(parse_osu(&exported).unwrap().beatmap.normal_notes, beatmap.normal_notes);
+    }
+
+    fn get_lane_notes(beatmap: &SongBeatmapFile) -> Vec<(usize, i64, i64)> {
+        let lane = |x: f32| FOUR_KEY_X.iter().position(|lx| *lx == x).unwrap();
+        let mut notes = beatmap
+            .normal_notes
+            .iter()
+            .map(|x| (lane(x.x), x.time as i64, x.time as i64))
+            .chain(
+                beatmap
+                    .long_notes
+                    .iter()
+                    .map(|x| (lane(x.x), x.start_time as i64, x.end_time as i64)),
+            )
+            .collect::<Vec<_>>();
+        notes.sort();
+        notes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let imported = parse_osu(CHART).unwrap().beatmap;
+        let exported = export_osu(&imported, "audio.mp3", Some(1000)).unwrap();
+        let osu = parse_osu(&exported).unwrap();
+        assert_eq!(osu.audio_filename, "audio.mp3");
+        assert_eq!(osu.preview_time, Some(1000));
+        let beatmap = osu.beatmap;
+        assert_eq!(beatmap.metadata.title, imported.metadata.title);
+        assert_eq!(beatmap.metadata.version, imported.metadata.version);
+        assert_eq!(beatmap.metadata.tags, imported.metadata.tags);
+        assert_eq!(beatmap.judge_level, imported.judge_level);
+        assert_eq!(beatmap.drain_level, imported.drain_level);
+        assert_eq!(get_lane_notes(&beatmap), get_lane_notes(&imported));
+
+        let timings = &beatmap.timing_group.timing_lines[0].timings;
+        let imported_timings = &imported.timing_group.timing_lines[0].timings;
+        assert_eq!(timings.len(), imported_timings.len());
+        for (a, b) in timings.iter().zip(imported_timings) {
+            assert_eq!(a.offset, b.offset);
+            assert_eq!(a.set_bpm, b.set_bpm);
+            assert_eq!(a.get_speed(), b.get_speed());
+        }
+    }
+
+    #[test]
+    fn test_export_editor_beatmap() {
+        let mut beatmap = SongBeatmapFile::new("Song".into());
+        beatmap.rule = MapRule::FourKey;
+        let time_signature = NonZeroU8::new(4).unwrap();
+        beatmap.timing_group.timing_lines[0].timings = vec![
+            Timing::new(Bpm::from(150.0), 100, time_signature),
+            Timing::new_speed(1.5, 900, time_signature),
+        ];
+        for (idx, x) in FOUR_KEY_X.iter().enumerate() {
+            beatmap.normal_notes.push(NormalNote {
+                x: *x,
+                width: 0.25,
+                time: 100 + 400 * idx as i64,
+                note_type: NoteHitType::Click,
+                timing_group: 0,
+            });
+        }
+        beatmap.long_notes.push(LongNote {
+            x: FOUR_KEY_X[2],
+            width: 0.25,
+            start_time: 1000,
+            end_time: 1800,
+            timing_group: 0,
+        });
+        beatmap.update();
+
+        let osu = parse_osu(&export_osu(&beatmap, "bgm.ogg", None).unwrap()).unwrap();
+        assert_eq!(get_lane_notes(&osu.beatmap), get_lane_notes(&beatmap));
+        let timings = &osu.beatmap.timing_group.timing_lines[0].timings;
+        assert_eq!(timings[0].get_bpm().to_string(), "150.00");
+        assert_eq!(timings[1].offset, 900);
+        assert_eq!(timings[1].set_speed, Some(1.5));
+
+        let mut grouped = beatmap.clone();
+        grouped.normal_notes[0].timing_group = 1;
+        assert!(export_osu(&grouped, "bgm.ogg", None).is_err());
+
+        beatmap.rule = MapRule::Falling;
+        assert!(export_osu(&beatmap, "bgm.ogg", None).is_err());
+    }
+}
@@ -1,9 +1,13 @@
 use crate::game::beatmap::file::SongBeatmapFile;
+use crate::game::beatmap::health::{HealthCounter, HealthRates, DEFAULT_DRAIN_LEVEL};
+use crate::game::beatmap::mods::PlayMods;
 use crate::game::beatmap::GamePos;
+use crate::game::replay::{ReplayInput, ReplayInputKind};
 use crate::game::note::{LongNote, NormalNote, Note, NoteExt, NoteHitType};
 use crate::game::timing::{TimingGroup, TimingLine};
 use crate::game::{offset_type_to_secs, secs_to_offset_type, GameTimeType, OffsetType};
 use egui::ahash::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -21,6 +25,21 @@ impl NoteResult {
     }
 }
 
+/// Which part of the note the judgement is for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum JudgePart {
+    /// The normal note.
+    Note,
+    /// The start of the long note, judged by the press.
+    Head,
+    /// The end of the long note, judged by the release.
+    Tail,
+}
+
+impl JudgePart {
+    pub const ALL: [JudgePart; 3] = [JudgePart::Note, JudgePart::Head, JudgePart::Tail];
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct NoteHitResult {
     pub grade: NoteResult,
@@ -39,16 +58,94 @@ impl NoteHitResult {
     }
 }
 
-#[derive(Copy, Clone)]
+/// The judged part of the note.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct NoteJudgement {
+    /// The index of the note in [`Gaming::raw_file`], the long notes follow the normal notes.
+    pub note_idx: usize,
+    pub part: JudgePart,
+    pub result: NoteHitResult,
+}
+
+/// The judge level used if the beatmap declares none, the windows of it are [`JudgeTimes::default`].
+pub const DEFAULT_JUDGE_LEVEL: f32 = 5.0;
+/// The config key of the judge preset overriding the judge level of the beatmap.
+pub const JUDGE_PRESET_KEY: &'static str = "judge_preset";
+
+/// The named judge level the player chooses to override the beatmap.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum JudgePreset {
+    Easy,
+    Normal,
+    Hard,
+    Expert,
+}
+
+impl JudgePreset {
+    pub const ALL: [JudgePreset; 4] = [
+        JudgePreset::Easy,
+        JudgePreset::Normal,
+        JudgePreset::Hard,
+        JudgePreset::Expert,
+    ];
+
+    /// The name in the config.
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            JudgePreset::Easy => "easy",
+            JudgePreset::Normal => "normal",
+            JudgePreset::Hard => "hard",
+            JudgePreset::Expert => "expert",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|x| x.get_name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn get_level(&self) -> f32 {
+        match self {
+            JudgePreset::Easy => 2.0,
+            JudgePreset::Normal => DEFAULT_JUDGE_LEVEL,
+            JudgePreset::Hard => 7.0,
+            JudgePreset::Expert => 9.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct JudgeTimes {
     pub perfect: OffsetType,
     pub great: OffsetType,
     pub good: OffsetType,
     pub bad: OffsetType,
     pub miss: OffsetType,
+    /// Releasing the long note earlier in it is judged as releasing at the end.
+    pub release: OffsetType,
 }
 
 impl JudgeTimes {
+    /// The windows of the judge level in 0 to 10, the higher the stricter.
+    /// Every level scales the default windows by 10%.
+    ///
+    /// The windows are in the beatmap time, they are scaled by the audio playback `rate` to keep
+    /// the same length in the real time.
+    pub fn from_level(level: f32, rate: f32) -> Self {
+        let base = Self::default();
+        let scale = (1.0 + (DEFAULT_JUDGE_LEVEL - level.clamp(0.0, 10.0)) * 0.1) * rate;
+        let scale = |x: OffsetType| (x as f32 * scale).round() as OffsetType;
+        Self {
+            perfect: scale(base.perfect),
+            great: scale(base.great),
+            good: scale(base.good),
+            bad: scale(base.bad),
+            miss: scale(base.miss),
+            release: scale(base.release),
+        }
+    }
+
     pub(crate) fn get_result(&self, click_time: OffsetType, hit_time: OffsetType) -> NoteHitResult {
         let delta = (click_time - hit_time).abs();
         // println!("Get result for delta {}", click_time - hit_time);
@@ -61,11 +158,22 @@ impl JudgeTimes {
         };
         NoteHitResult::new(grade, click_time - hit_time)
     }
+
+    /// Judge the release of the long note end at `end_time`, the early release in the
+    /// [`Self::release`] tolerance is the same as releasing at the end.
+    pub(crate) fn get_release_result(&self, release_time: OffsetType, end_time: OffsetType) -> NoteHitResult {
+        let release_time = (release_time + self.release).min(end_time);
+        self.get_result(release_time, end_time)
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PlayOptions {
     pub default_view_time: f32,
+    /// Override the judge level of the beatmap.
+    pub judge_preset: Option<JudgePreset>,
+    /// The mods of the play, see [`PlayMods`].
+    pub mods: PlayMods,
 }
 
 impl Default for JudgeTimes {
@@ -76,14 +184,24 @@ impl Default for JudgeTimes {
             good: 100,
             bad: 150,
             miss: 200,
+            release: 50,
         }
     }
 }
 
+impl PlayOptions {
+    /// The y scale of the notes, the view time with the scroll speed.
+    pub fn get_view_time(&self) -> f32 {
+        self.default_view_time * self.mods.get_scroll_speed()
+    }
+}
+
 impl Default for PlayOptions {
     fn default() -> Self {
         Self {
             default_view_time: 1.0,
+            judge_preset: None,
+            mods: PlayMods::default(),
         }
     }
 }
@@ -93,7 +211,10 @@ pub struct ScoreCounter {
     max_combo: u32,
     combo: u32,
     result_map: HashMap<NoteResult, u32>,
+    part_map: HashMap<(JudgePart, NoteResult), u32>,
+    /// The hit delays of the notes and the long note heads.
     deltas: Vec<OffsetType>,
+    mods: PlayMods,
 }
 
 pub struct PlayingNote<NoteType> {
@@ -102,6 +223,8 @@ pub struct PlayingNote<NoteType> {
     pub note_y: f32,
     pub note_end_y: f32,
     pub start_result: Option<NoteHitResult>,
+    /// The tail result of the long note, set when judged before the end.
+    pub end_result: Option<NoteHitResult>,
     // the pointer holding this note.
     holding: HashSet<u64>,
 }
@@ -112,14 +235,17 @@ pub enum PlayingNoteType<'a> {
 }
 
 impl ScoreCounter {
-    pub fn accept_result(&mut self, result: NoteHitResult) {
+    pub fn accept_result(&mut self, part: JudgePart, result: NoteHitResult) {
         if result.grade == NoteResult::Miss {
             self.combo = 0;
         } else {
             self.combo += 1;
         }
         *self.result_map.get_mut(&result.grade).unwrap() += 1;
-        self.deltas.push(result.delta);
+        *self.part_map.entry((part, result.grade)).or_default() += 1;
+        if part != JudgePart::Tail {
+            self.deltas.push(result.delta);
+        }
         self.max_combo = self.combo.max(self.max_combo);
     }
 
@@ -137,10 +263,14 @@ impl ScoreCounter {
     pub fn get_note_count(&self, result: NoteResult) -> u32 {
         self.result_map[&result]
     }
+    pub fn get_part_count(&self, part: JudgePart, result: NoteResult) -> u32 {
+        self.part_map.get(&(part, result)).copied().unwrap_or(0)
+    }
     pub fn get_max_combo(&self) -> u32 {
         self.max_combo
     }
-    pub fn new(total_result: u32) -> Self {
+    /// The `total_result` counts the long note twice for the head and the tail.
+    pub fn new(total_result: u32, mods: PlayMods) -> Self {
         let mut result_map = HashMap::default();
         result_map.insert(NoteResult::Miss, 0);
         result_map.insert(NoteResult::Bad, 0);
@@ -152,10 +282,25 @@ impl ScoreCounter {
             max_combo: 0,
             combo: 0,
             result_map,
+            part_map: Default::default(),
             deltas: Vec::with_capacity(total_result as usize),
+            mods,
         }
     }
 
+    pub fn get_mods(&self) -> &PlayMods {
+        &self.mods
+    }
+
+    pub fn get_multiplier(&self) -> f32 {
+        self.mods.get_multiplier()
+    }
+
+    /// The score with the multiplier of the mods.
+    pub fn get_mod_score(&self) -> u32 {
+        (self.get_score() as f32 * self.get_multiplier()).round() as u32
+    }
+
     pub fn get_score(&self) -> u32 {
         if self.total_result == 0 {
             return 0;
@@ -180,6 +325,7 @@ impl<T: Note> PlayingNote<T> {
             note_y,
             note_end_y,
             start_result: None,
+            end_result: None,
             holding: Default::default(),
         }
     }
@@ -231,6 +377,13 @@ impl<T: Note> Note for PlayingNote<T> {
 }
 
 impl PlayingNoteType<'_> {
+    pub fn get_note_idx(&self) -> usize {
+        match self {
+            PlayingNoteType::Normal(x) => x.note_idx,
+            PlayingNoteType::Long(x) => x.note_idx,
+        }
+    }
+
     fn get_note(&self) -> &dyn Note {
         match self {
             PlayingNoteType::Normal(x) => &x.note,
@@ -288,20 +441,21 @@ impl<T> Default for TrackNotes<T> {
 }
 impl<T: Note> TrackNotes<T> {
     /// Tick the track.
-    /// The callback will be called before the start result set.
+    /// The callback will be called with the judged part, the long note tail is judged when it is
+    /// held to the end or the head is missed.
     pub fn tick(
         &mut self,
         ops: &PlayOptions,
         judge_times: &JudgeTimes,
         game_time: GameTimeType,
         gameplay_y: f32,
-        mut callback: impl FnMut(&mut PlayingNote<T>, NoteHitResult),
+        mut callback: impl FnMut(&mut PlayingNote<T>, JudgePart, NoteHitResult),
     ) {
         let offset = secs_to_offset_type(game_time);
 
         // move pending to play area for some lag case.
         while let Some(note) = self.pending.front() {
-            if note.note.get_time() <= secs_to_offset_type(game_time + ops.default_view_time as GameTimeType + 1.0)
+            if note.note.get_time() <= secs_to_offset_type(game_time + ops.get_view_time() as GameTimeType + 1.0)
                 || (note.note_y - gameplay_y).abs() < 2.0
                 || (note.note_end_y - gameplay_y).abs() < 2.0
             {
@@ -315,17 +469,30 @@ impl<T: Note> TrackNotes<T> {
             }
         }
         self.play_area.retain_mut(|x| {
+            let is_long = x.get_end_time().is_some();
             if x.is_later_miss(&judge_times, offset) {
-                callback(x, NoteHitResult::new(NoteResult::Miss, judge_times.miss));
-                if x.get_end_time().is_some() {
-                    x.start_result = Some(NoteHitResult::new(NoteResult::Miss, judge_times.miss));
+                let miss = NoteHitResult::new(NoteResult::Miss, judge_times.miss);
+                if is_long {
+                    // the tail is missed with the head, we keep it to render until the end.
+                    x.start_result = Some(miss);
+                    callback(x, JudgePart::Head, miss);
+                    x.end_result = Some(miss);
+                    callback(x, JudgePart::Tail, miss);
                     true
                 } else {
+                    callback(x, JudgePart::Note, miss);
                     false
                 }
             } else if let Some(r) = x.start_result {
                 if x.get_end_time_or_time() <= offset {
-                    callback(x, r);
+                    if !is_long {
+                        callback(x, JudgePart::Note, r);
+                    } else if x.end_result.is_none() {
+                        // held to the end.
+                        let result = NoteHitResult::new(NoteResult::Perfect, 0);
+                        x.end_result = Some(result);
+                        callback(x, JudgePart::Tail, result);
+                    }
                     false
                 } else {
                     true
@@ -351,81 +518,120 @@ pub struct Gaming {
     pub normal_notes: Vec<TrackNotes<NormalNote>>,
     pub long_notes: Vec<TrackNotes<LongNote>>,
     pointers: HashMap<u64, GamePos>,
+    inputs: Vec<ReplayInput>,
     pub score_counter: ScoreCounter,
+    pub health: HealthCounter,
+    /// The judgements made when taking the inputs, sent by the next tick.
+    pending_judgements: Vec<NoteJudgement>,
+    /// Hit every note perfectly, from [`PlayMods::auto_play`].
     pub auto_play: bool,
 }
 
 impl Gaming {
-    fn tick_tracks(
-        &mut self,
-        game_time: GameTimeType,
-        mut callback: Option<impl FnMut(PlayingNoteType, NoteHitResult)>,
-    ) {
+    /// Judge the notes passed at the `game_time`, return the accepted judgements in the judged
+    /// time order.
+    fn tick_tracks(&mut self, game_time: GameTimeType) -> Vec<NoteJudgement> {
+        // the judged time and the judgement.
+        let mut judged = vec![];
+        let mut push = |note: &PlayingNote<_>, part, result| {
+            let judgement = NoteJudgement {
+                note_idx: note.note_idx,
+                part,
+                result,
+            };
+            judged.push((get_judged_time(note, part, result), judgement));
+        };
         for (x, tl) in self.normal_notes.iter_mut().zip(self.raw_file.timing_group.timing_lines.iter()) {
-            let y = tl.get_y_f32(game_time as f32);
+            let y = tl.get_y_f32(game_time as f32) * self.ops.get_view_time();
             if self.auto_play {
                 while let Some(note) = x.play_area.front_mut() {
                     let delta = offset_type_to_secs(note.note.time) - game_time;
                     if delta <= 0.001 {
-                        let result = NoteHitResult::new(NoteResult::Perfect, 0);
-                        if let Some(cb) = &mut callback {
-                            cb(PlayingNoteType::Normal(note), result);
-                        }
-                        self.score_counter.accept_result(result);
-
+                        push(note, JudgePart::Note, NoteHitResult::new(NoteResult::Perfect, 0));
                         x.play_area.pop_front();
                     } else {
                         break;
                     }
                 }
             }
-            x.tick(&self.ops, &self.judge, game_time, y, |note, result| {
-                self.score_counter.accept_result(result);
-                if let Some(cb) = &mut callback {
-                    cb(PlayingNoteType::Normal(note), result);
-                }
-            });
+            x.tick(&self.ops, &self.judge, game_time, y, |note, part, result| push(note, part, result));
         }
+        let mut judged_long = vec![];
+        let mut push = |note: &PlayingNote<_>, part, result| {
+            let judgement = NoteJudgement {
+                note_idx: note.note_idx,
+                part,
+                result,
+            };
+            judged_long.push((get_judged_time(note, part, result), judgement));
+        };
         for (x, tl) in self.long_notes.iter_mut().zip(self.raw_file.timing_group.timing_lines.iter()) {
-            let y = tl.get_y_f32(game_time as f32);
+            let y = tl.get_y_f32(game_time as f32) * self.ops.get_view_time();
             if self.auto_play {
                 x.play_area.retain_mut(|note| {
                     let delta = offset_type_to_secs(note.note.start_time) - game_time;
                     let end_delta = offset_type_to_secs(note.note.end_time) - game_time;
                     if delta <= 0.001 && note.start_result.is_none() {
                         let result = NoteHitResult::new(NoteResult::Perfect, 0);
-                        if let Some(cb) = &mut callback {
-                            cb(PlayingNoteType::Long(note), result);
-                        }
                         note.start_result = Some(result);
+                        push(note, JudgePart::Head, result);
                     }
                     if end_delta <= 0.001 {
-                        let result = NoteHitResult::new(NoteResult::Perfect, 0);
-                        self.score_counter.accept_result(result);
-                        if let Some(cb) = &mut callback {
-                            cb(PlayingNoteType::Long(note), result);
+                        if note.end_result.is_none() {
+                            let result = NoteHitResult::new(NoteResult::Perfect, 0);
+                            note.end_result = Some(result);
+                            push(note, JudgePart::Tail, result);
                         }
-                        note.start_result = Some(result);
                         return false;
                     }
                     true
                 });
             }
-            x.tick(&self.ops, &self.judge, game_time, y, |note, result| {
-                self.score_counter.accept_result(result);
-                if let Some(cb) = &mut callback {
-                    cb(PlayingNoteType::Long(note), result);
-                }
-            });
+            x.tick(&self.ops, &self.judge, game_time, y, |note, part, result| push(note, part, result));
+        }
+        judged.append(&mut judged_long);
+        // the stable sort keeps the order of the same time.
+        judged.sort_by_key(|x| x.0);
+        judged
+            .into_iter()
+            .filter(|(time, judgement)| self.accept_judgement(*time, *judgement))
+            .map(|x| x.1)
+            .collect()
+    }
+
+    /// Count the judgement made at the `time`, nothing is counted after failed.
+    fn accept_judgement(&mut self, time: OffsetType, judgement: NoteJudgement) -> bool {
+        if self.health.is_failed() {
+            return false;
         }
+        self.score_counter.accept_result(judgement.part, judgement.result);
+        self.health.accept_result(judgement.result.grade, time);
+        true
     }
 
-    pub fn load_game(mut file: SongBeatmapFile) -> Self {
-        let judge = JudgeTimes::default();
-        let ops = PlayOptions::default();
+    /// Judge the notes passed before the input at the `time`, so the judged order only depends on
+    /// the inputs rather than how often the game ticks. The judgements are sent by the next tick.
+    ///
+    /// The slide notes should be caught until the `time` before this.
+    fn judge_until(&mut self, time: OffsetType) {
+        let judgements = self.tick_tracks(offset_type_to_secs(time));
+        self.pending_judgements.extend(judgements);
+    }
+
+    pub fn load_game(mut file: SongBeatmapFile, ops: PlayOptions) -> Self {
+        let level = ops
+            .judge_preset
+            .map(|x| x.get_level())
+            .or(file.judge_level)
+            .unwrap_or(DEFAULT_JUDGE_LEVEL);
+        let judge = JudgeTimes::from_level(level, ops.mods.get_rate());
+        let health_rates = HealthRates::from_level(file.drain_level.unwrap_or(DEFAULT_DRAIN_LEVEL));
 
         file.normal_notes.sort_by_key(|x| x.time);
         file.long_notes.sort_by_key(|x| x.start_time);
+        // the raw file is kept unmodded for the replay hash.
+        let raw_file = file.clone();
+        ops.mods.apply(&mut file);
 
         fn add_notes<T: Note + Copy>(
             notes: &[T],
@@ -454,7 +660,7 @@ impl Gaming {
             &file.normal_notes,
             &mut normal_notes,
             &file.timing_group,
-            ops.default_view_time,
+            ops.get_view_time(),
             &mut total_notes,
         );
         let mut long_notes = vec![];
@@ -462,32 +668,90 @@ impl Gaming {
             &file.long_notes,
             &mut long_notes,
             &file.timing_group,
-            ops.default_view_time,
+            ops.get_view_time(),
             &mut total_notes,
         );
 
         Self {
-            raw_file: file.clone(),
+            raw_file,
             ops,
             judge,
             normal_notes,
             long_notes,
             pointers: Default::default(),
-            score_counter: ScoreCounter::new(total_notes as u32),
-            auto_play: false,
+            inputs: vec![],
+            // the long note is judged for the head and the tail.
+            score_counter: ScoreCounter::new((total_notes + file.long_notes.len()) as u32, ops.mods),
+            health: HealthCounter::new(health_rates, ops.mods.no_fail),
+            pending_judgements: vec![],
+            auto_play: ops.mods.auto_play,
         }
     }
 
-    pub fn tick(
-        &mut self,
-        game_time: GameTimeType,
-        callback: Option<impl FnMut(PlayingNoteType, NoteHitResult)>,
-    ) {
-        self.tick_tracks(game_time, callback);
+    pub fn get_judge_times(&self) -> &JudgeTimes {
+        &self.judge
+    }
+
+    /// Judge the notes passed at the `game_time`, the callback gets the judgements in the judged
+    /// order. Nothing is judged after failed.
+    pub fn tick(&mut self, game_time: GameTimeType, callback: Option<impl FnMut(NoteJudgement)>) {
+        let mut judgements = std::mem::take(&mut self.pending_judgements);
+        if !self.health.is_failed() {
+            if !self.auto_play {
+                self.catch_slides(secs_to_offset_type(game_time));
+            }
+            judgements.extend(self.tick_tracks(game_time));
+        }
+        if let Some(mut callback) = callback {
+            judgements.into_iter().for_each(&mut callback);
+        }
+    }
+
+    /// Catch the slide notes under the holding pointers until the `time`.
+    ///
+    /// The pointer catches the note once it is on the note in the bad window. The caught note is
+    /// judged when it reaches the judgement line, so catching early is perfect and catching late
+    /// is judged by the delay. The result is sent by the tick callback.
+    ///
+    /// The catch time only depends on the inputs rather than when this is called, so it should
+    /// be called before any pointer changes.
+    fn catch_slides(&mut self, time: OffsetType) {
+        let judge = &self.judge;
+        let pointers = &self.pointers;
+        for note in self.normal_notes.iter_mut().flat_map(|x| x.play_area.iter_mut()) {
+            if note.note.note_type != NoteHitType::Slide || note.start_result.is_some() {
+                continue;
+            }
+            let caught = pointers
+                .values()
+                .filter(|x| note.is_x_in_range(x.x))
+                .map(|x| x.time.max(note.note.time - judge.bad))
+                .min()
+                .filter(|x| *x <= time && *x <= note.note.time + judge.bad);
+            if let Some(caught) = caught {
+                let result = judge.get_result(caught.max(note.note.time), note.note.time);
+                note.start_result = Some(result);
+            }
+        }
+    }
+
+    /// The inputs received in order, see [`crate::game::replay`].
+    pub fn get_inputs(&self) -> &[ReplayInput] {
+        &self.inputs
     }
 
-    /// return the note hit result, and if it is long start.
-    pub fn process_input(&mut self, input: GamePos, pointer: u64) -> Option<(NoteHitResult, bool)> {
+    /// return the judgement of the note hit, the part is [`JudgePart::Head`] for the long start.
+    /// Only the click notes are hit here, the slide notes are judged in [`Self::tick`].
+    pub fn process_input(&mut self, input: GamePos, pointer: u64) -> Option<NoteJudgement> {
+        if self.health.is_failed() {
+            return None;
+        }
+        self.inputs.push(ReplayInput::new(ReplayInputKind::Press, input, pointer));
+        self.catch_slides(input.time);
+        self.judge_until(input.time);
+        if self.health.is_failed() {
+            return None;
+        }
         let time_range = input.time - self.judge.bad..=input.time + self.judge.miss;
         let long_time_range = input.time - self.judge.bad..=input.time + self.judge.bad;
         let in_time_range = |time: OffsetType| time_range.contains(&time);
@@ -498,7 +762,7 @@ impl Gaming {
             .normal_notes
             .par_iter_mut()
             .flat_map(|x| x.play_area.par_iter_mut())
-            .filter(|x| in_time_range(x.note.time))
+            .filter(|x| x.note.note_type == NoteHitType::Click && in_time_range(x.note.time))
             .map(|x| PlayingNoteType::Normal(x))
             .chain(self.long_notes.par_iter_mut().flat_map(|x| {
                 x.play_area
@@ -524,20 +788,27 @@ impl Gaming {
                     let idx = note.note_idx;
                     let tg = note.get_timing_group() as usize;
                     self.normal_notes[tg].remove_play_note(idx);
-                    self.score_counter.accept_result(result);
-                    ret = Some((result, false));
+                    ret = Some(NoteJudgement {
+                        note_idx: idx,
+                        part: JudgePart::Note,
+                        result,
+                    });
                 }
                 PlayingNoteType::Long(note) => {
                     let result = self.judge.get_result(input.time, note.get_time());
                     note.start_result = Some(result);
-                    ret = Some((result, true));
-                    // let idx = note.note_idx;
-                    // let tg = note.get_timing_group() as usize;
                     // we remove it when end.
-                    // self.long_notes[tg].remove_play_note(idx);
+                    ret = Some(NoteJudgement {
+                        note_idx: note.note_idx,
+                        part: JudgePart::Head,
+                        result,
+                    });
                 }
             };
         }
+        if let Some(judgement) = ret {
+            self.accept_judgement(input.time, judgement);
+        }
         self.pointers.insert(pointer, input);
 
         self.long_notes
@@ -547,10 +818,47 @@ impl Gaming {
             .for_each(|playing_note| {
                 playing_note.holding.insert(pointer);
             });
+        self.catch_slides(input.time);
 
         ret
     }
 
+    /// Release all the holding pointers at the `time`, when their releases will be lost, e.g.
+    /// the game is paused.
+    pub fn release_all(&mut self, time: OffsetType) {
+        let mut pointers = self.pointers.iter().map(|(p, pos)| (*p, pos.x)).collect::<Vec<_>>();
+        pointers.sort_by_key(|x| x.0);
+        for (pointer, x) in pointers {
+            self.process_input_leave(GamePos::new(x, time), pointer);
+        }
+    }
+
+    /// The holding pointer moved, the slides under its last position are caught before moving,
+    /// the slides it enters are caught by the next tick or input.
+    pub fn process_input_move(&mut self, input: GamePos, pointer: u64) {
+        if self.health.is_failed() {
+            return;
+        }
+        self.inputs.push(ReplayInput::new(ReplayInputKind::Move, input, pointer));
+        self.catch_slides(input.time);
+        self.judge_until(input.time);
+        if self.health.is_failed() || !self.pointers.contains_key(&pointer) {
+            return;
+        }
+        self.pointers.insert(pointer, input);
+        let released = self.release_long_notes(pointer, input.time, |note| !note.is_x_in_range(input.x));
+        self.pending_judgements.extend(released);
+
+        use rayon::iter::*;
+        self.long_notes
+            .par_iter_mut()
+            .flat_map(|x| x.play_area.par_iter_mut())
+            .filter(|x| x.is_x_in_range(input.x))
+            .for_each(|playing_note| {
+                playing_note.holding.insert(pointer);
+            });
+    }
+
     pub fn is_end(&self) -> bool {
         self.normal_notes
             .iter()
@@ -561,44 +869,86 @@ impl Gaming {
                 .all(|x| x.pending.is_empty() && x.play_area.is_empty())
     }
 
-    pub fn process_input_leave(
-        &mut self,
-        input: GamePos,
-        pointer: u64,
-    ) -> Option<(NoteHitResult, PlayingNoteType)> {
+    /// Release the pointer, return the tail result if the long note is no longer held, the other
+    /// tails are sent by the next tick.
+    ///
+    /// The tail is judged by the release time, releasing too early is a miss and breaks the combo.
+    pub fn process_input_leave(&mut self, input: GamePos, pointer: u64) -> Option<NoteJudgement> {
+        if self.health.is_failed() {
+            return None;
+        }
+        self.inputs.push(ReplayInput::new(ReplayInputKind::Release, input, pointer));
+        self.catch_slides(input.time);
+        self.judge_until(input.time);
+        if self.health.is_failed() {
+            return None;
+        }
         self.pointers.remove(&pointer);
+        let mut released = self.release_long_notes(pointer, input.time, |_| true).into_iter();
+        let first = released.next();
+        // the other tails released together are sent by the next tick.
+        self.pending_judgements.extend(released);
+        first
+    }
 
+    /// Remove the pointer from the long notes it holds and `should_release`, return the accepted
+    /// tail results of the notes no longer held.
+    fn release_long_notes(
+        &mut self,
+        pointer: u64,
+        time: OffsetType,
+        should_release: impl Fn(&PlayingNote<LongNote>) -> bool + Sync,
+    ) -> Vec<NoteJudgement> {
         use rayon::iter::*;
-        self.long_notes
+        let results = self
+            .long_notes
             .par_iter_mut()
             .flat_map(|x| x.play_area.par_iter_mut())
-            .filter(|x| x.is_x_in_range(input.x))
-            .for_each(|playing_note| {
+            .filter(|x| x.holding.contains(&pointer) && should_release(x))
+            .filter_map(|playing_note| {
                 playing_note.holding.remove(&pointer);
-                if playing_note.holding.is_empty() {
-                    if let Some(start_result) = playing_note.start_result {
-                        for (p, input) in &self.pointers {
-                            if playing_note.is_x_in_range(input.x) {
-                                playing_note.holding.insert(*p);
-                            }
-                        }
-
-                        if playing_note.holding.is_empty() && !self.auto_play {
-                            let cur_result = self
-                                .judge
-                                .get_result(input.time, playing_note.note.end_time);
-                            if cur_result.grade != NoteResult::Perfect {
-                                playing_note.start_result =
-                                    Some(NoteHitResult::new(NoteResult::Miss, start_result.delta));
-                            }
-                        }
+                if !playing_note.holding.is_empty()
+                    || playing_note.start_result.is_none()
+                    || playing_note.end_result.is_some()
+                {
+                    return None;
+                }
+                for (p, input) in &self.pointers {
+                    if playing_note.is_x_in_range(input.x) {
+                        playing_note.holding.insert(*p);
                     }
                 }
-            });
-        None
+                if !playing_note.holding.is_empty() || self.auto_play {
+                    return None;
+                }
+                let result = self
+                    .judge
+                    .get_release_result(time, playing_note.note.end_time);
+                playing_note.end_result = Some(result);
+                Some(NoteJudgement {
+                    note_idx: playing_note.note_idx,
+                    part: JudgePart::Tail,
+                    result,
+                })
+            })
+            .collect::<Vec<_>>();
+        results
+            .into_iter()
+            .filter(|judgement| self.accept_judgement(time, *judgement))
+            .collect()
     }
 }
 
+/// The time the part is judged at, the time of the part with the delay of the result.
+fn get_judged_time<T: Note>(note: &PlayingNote<T>, part: JudgePart, result: NoteHitResult) -> OffsetType {
+    let time = match part {
+        // the tail missed with the head is judged with the head.
+        JudgePart::Tail if !note.start_result.is_some_and(|x| x.is_miss()) => note.get_end_time_or_time(),
+        _ => note.get_time(),
+    };
+    time + result.delta
+}
+
 macro_rules! impl_from_note {
     ($ty: ty, $tk: ident) => {
         impl<'a> From<&'a mut PlayingNote<$ty>> for PlayingNoteType<'a> {
@@ -610,3 +960,194 @@ macro_rules! impl_from_note {
 }
 impl_from_note!(NormalNote, Normal);
 impl_from_note!(LongNote, Long);
+
+#[cfg(test)]
+mod test {
+    use crate::game::beatmap::file::SongBeatmapFile;
+    use crate::game::beatmap::play::{
+        Gaming, JudgePart, NoteJudgement, NoteResult, PlayOptions,
+    };
+    use crate::game::beatmap::{GamePos, FOUR_KEY_X};
+    use crate::game::beatmap::test::{lane_long_note, lane_note};
+    use crate::game::note::NoteHitType;
+    use crate::game::{offset_type_to_secs, OffsetType};
+
+    fn load_game(
+        notes: &[(usize, OffsetType, NoteHitType)],
+        long_notes: &[(usize, OffsetType, OffsetType)],
+    ) -> Gaming {
+        let mut beatmap = SongBeatmapFile::new("Song".into());
+        beatmap.normal_notes = notes
+            .iter()
+            .map(|(lane, time, note_type)| lane_note(*lane, *time, *note_type))
+            .collect();
+        beatmap.long_notes = long_notes
+            .iter()
+            .map(|(lane, start_time, end_time)| lane_long_note(*lane, *start_time, *end_time))
+            .collect();
+        beatmap.update();
+        Gaming::load_game(beatmap, PlayOptions::default())
+    }
+
+    enum Input {
+        Press(usize),
+        Move(usize),
+        Release(usize),
+        Tick,
+    }
+
+    /// Feed the timed inputs of one pointer, return the results in the judged order.
+    fn play(game: &mut Gaming, inputs: &[(OffsetType, Input)]) -> Vec<NoteResult> {
+        let mut results = vec![];
+        for (time, input) in inputs {
+            game.tick(
+                offset_type_to_secs(*time),
+                Some(|judgement: NoteJudgement| results.push(judgement.result.grade)),
+            );
+            let pos = |lane: &usize| GamePos::new(FOUR_KEY_X[*lane], *time);
+            match input {
+                Input::Press(lane) => {
+                    if let Some(judgement) = game.process_input(pos(lane), 0) {
+                        results.push(judgement.result.grade);
+                    }
+                }
+                Input::Move(lane) => game.process_input_move(pos(lane), 0),
+                Input::Release(lane) => {
+                    if let Some(judgement) = game.process_input_leave(pos(lane), 0) {
+                        results.push(judgement.result.grade);
+                    }
+                }
+                Input::Tick => {}
+            }
+        }
+        results
+    }
+
+    #[test]
+    fn test_slide_held() {
+        use Input::*;
+        let mut game = load_game(&[(0, 1000, NoteHitType::Slide), (0, 1200, NoteHitType::Slide)], &[]);
+        // held before the window, judged when the note arrives.
+        let results = play(
+            &mut game,
+            &[(0, Press(0)), (990, Tick), (1000, Tick), (1150, Tick), (1200, Tick)],
+        );
+        assert_eq!(results, [NoteResult::Perfect, NoteResult::Perfect]);
+        assert!(game.is_end());
+    }
+
+    #[test]
+    fn test_slide_enter() {
+        use Input::*;
+        let mut game = load_game(
+            &[
+                (0, 1000, NoteHitType::Slide),
+                (1, 2000, NoteHitType::Slide),
+                (2, 3000, NoteHitType::Slide),
+            ],
+            &[],
+        );
+        let results = play(
+            &mut game,
+            &[
+                (0, Press(3)),
+                // enter late.
+                (1040, Move(0)),
+                (1050, Tick),
+                // enter early.
+                (1900, Move(1)),
+                (2000, Tick),
+                // enter after the window.
+                (3200, Move(2)),
+                (3300, Tick),
+            ],
+        );
+        assert_eq!(results, [NoteResult::Great, NoteResult::Perfect, NoteResult::Miss]);
+
+        // the released pointer catches nothing.
+        let mut game = load_game(&[(0, 1000, NoteHitType::Slide)], &[]);
+        let results = play(
+            &mut game,
+            &[(0, Press(1)), (500, Release(1)), (1000, Move(0)), (1300, Tick)],
+        );
+        assert_eq!(results, [NoteResult::Miss]);
+    }
+
+    #[test]
+    fn test_slide_with_click() {
+        use Input::*;
+        // the press hits the click and catches the slide in the same lane together.
+        let mut game = load_game(
+            &[(0, 1000, NoteHitType::Click), (0, 1000, NoteHitType::Slide)],
+            &[],
+        );
+        let results = play(&mut game, &[(500, Tick), (1010, Press(0)), (1020, Tick)]);
+        assert_eq!(results, [NoteResult::Perfect, NoteResult::Perfect]);
+        assert_eq!(game.score_counter.get_note_count(NoteResult::Perfect), 2);
+    }
+
+    #[test]
+    fn test_long_note_tail() {
+        use Input::*;
+        use NoteResult::*;
+        let hold = |release: OffsetType| {
+            let mut game = load_game(&[], &[(0, 1000, 2000)]);
+            let results = play(
+                &mut game,
+                &[(500, Tick), (1000, Press(0)), (release, Release(0)), (2100, Tick)],
+            );
+            (results, game)
+        };
+        // held to the end, the release after it judges nothing.
+        let (results, game) = hold(2050);
+        assert_eq!(results, [Perfect, Perfect]);
+        assert_eq!(game.score_counter.get_score(), 1_000_000);
+        assert!(game.is_end());
+        // released a little early.
+        assert_eq!(hold(1960).0, [Perfect, Perfect]);
+        assert_eq!(hold(1900).0, [Perfect, Great]);
+
+        // dropped, the combo breaks at once.
+        let (results, game) = hold(1500);
+        assert_eq!(results, [Perfect, Miss]);
+        assert_eq!(game.score_counter.get_combo(), 0);
+        assert_eq!(game.score_counter.get_max_combo(), 1);
+        assert_eq!(game.score_counter.get_part_count(JudgePart::Head, Perfect), 1);
+        assert_eq!(game.score_counter.get_part_count(JudgePart::Tail, Miss), 1);
+        assert!(game.is_end());
+
+        // the tail is missed with the head.
+        let mut game = load_game(&[], &[(0, 1000, 2000)]);
+        let results = play(&mut game, &[(500, Tick), (1300, Tick), (2100, Tick)]);
+        assert_eq!(results, [Miss, Miss]);
+        assert!(game.is_end());
+    }
+
+    #[test]
+    fn test_release_all() {
+        use Input::*;
+        use NoteResult::*;
+        let mut game = load_game(&[], &[(0, 1000, 2000)]);
+        let results = play(&mut game, &[(1000, Press(0)), (1500, Tick)]);
+        assert_eq!(results, [Perfect]);
+        // paused in the long note, the tail is released early.
+        game.release_all(1500);
+        assert_eq!(game.score_counter.get_part_count(JudgePart::Tail, Miss), 1);
+        game.release_all(1600);
+        assert_eq!(game.get_inputs().len(), 2);
+    }
+
+    #[test]
+    fn test_release_overlapped() {
+        use Input::*;
+        use NoteResult::*;
+        // one pointer holds both the long notes, the release judges both tails.
+        let mut game = load_game(&[], &[(0, 1000, 3000), (0, 1100, 3000)]);
+        let results = play(
+            &mut game,
+            &[(1000, Press(0)), (1100, Press(0)), (2000, Release(0)), (2010, Tick)],
+        );
+        assert_eq!(results, [Perfect, Perfect, Miss, Miss]);
+        assert_eq!(game.score_counter.get_part_count(JudgePart::Tail, Miss), 2);
+    }
+}
@@ -0,0 +1,592 @@
+//! Convert StepMania `.sm` / `.ssc` charts into [`SongBeatmapFile`].
+//!
+//! Only `dance-single` charts are read, and only `#BPMS` and `#STOPS` are used for timing.
+//! Other gimmicks (delays, warps, scrolls...) are ignored.
+
+use crate::game::OffsetType;
+use crate::game::beatmap::file::SongBeatmapFile;
+use crate::game::beatmap::{FOUR_KEY_X, MapRule};
+use crate::game::note::{LongNote, NormalNote, NoteHitType};
+use crate::game::timing::{Bpm, Timing};
+use anyhow::anyhow;
+use std::num::NonZeroU8;
+
+pub const SM_EXTS: [&'static str; 2] = ["sm", "ssc"];
+
+const SM_STEPS_TYPE: &str = "dance-single";
+const BEATS_PER_MEASURE: f64 = 4.0;
+
+pub struct StepManiaSong {
+    /// The music file name relative to the chart file.
+    pub music: String,
+    /// The preview start point in ms, if the chart set.
+    pub sample_start: Option<OffsetType>,
+    /// The preview length in ms, if the chart set.
+    pub sample_length: Option<OffsetType>,
+    /// The background image file name relative to the chart file, if the chart set.
+    pub background: Option<String>,
+    /// The beatmap for every difficulty.
+    pub charts: Vec<SongBeatmapFile>,
+}
+
+/// The tags of one chart, the timing tags only exist in `.ssc` split timing.
+#[derive(Default)]
+struct SmChart {
+    steps_type: String,
+    description: String,
+    difficulty: String,
+    credit: String,
+    notes: String,
+    offset: Option<String>,
+    bpms: Option<String>,
+    stops: Option<String>,
+}
+
+struct SmTiming {
+    /// The time in ms of beat 0.
+    start_time: f64,
+    /// (beat, bpm) sorted by beat.
+    bpms: Vec<(f64, f64)>,
+    /// (beat, seconds) sorted by beat.
+    stops: Vec<(f64, f64)>,
+}
+
+/// Read all `#TAG:VALUE;` pairs, the tag is in upper case.
+fn parse_tags(data: &str) -> Vec<(String, String)> {
+    let data = data
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut tags = vec![];
+    let mut chars = data.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '#' {
+            continue;
+        }
+        let mut tag = String::new();
+        while let Some(c) = chars.next_if(|x| *x != ':' && *x != ';') {
+            tag.push(c);
+        }
+        if chars.next_if_eq(&':').is_none() {
+            continue;
+        }
+        let mut value = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                ';' => break,
+                '\\' => value.extend(chars.next()),
+                '\n' => {
+                    value.push(c);
+                    while let Some(c) = chars.next_if(|x| x.is_whitespace()) {
+                        value.push(c);
+                    }
+                    // the value without `;`, stepmania treats the `#` at line start as the next tag.
+                    if chars.peek() == Some(&'#') {
+                        break;
+                    }
+                }
+                c => value.push(c),
+            }
+        }
+        tags.push((tag.trim().to_uppercase(), value.trim().to_string()));
+    }
+    tags
+}
+
+/// Parse `beat=value,beat=value` list sorted by beat.
+fn parse_beat_values(value: &str) -> anyhow::Result<Vec<(f64, f64)>> {
+    let mut result = value
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|x| -> anyhow::Result<(f64, f64)> {
+            let (beat, value) = x
+                .split_once('=')
+                .ok_or(anyhow!("Invalid beat value {}", x))?;
+            Ok((beat.trim().parse()?, value.trim().parse()?))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    result.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(result)
+}
+
+impl SmTiming {
+    fn new(offset: &str, bpms: &str, stops: &str) -> anyhow::Result<Self> {
+        let offset = if offset.is_empty() {
+            0.0
+        } else {
+            offset.parse::<f64>()?
+        };
+        let bpms = parse_beat_values(bpms)?;
+        if bpms.is_empty() {
+            return Err(anyhow!("No bpm found"));
+        }
+        if let Some((_, bpm)) = bpms.iter().find(|(_, bpm)| *bpm <= 0.0 || !bpm.is_finite()) {
+            return Err(anyhow!("Unsupported bpm {}", bpm));
+        }
+        // negative stops are used as warps, which we cannot play.
+        let stops = parse_beat_values(stops)?
+            .into_iter()
+            .filter(|(_, secs)| *secs > 0.0)
+            .collect();
+        Ok(Self {
+            start_time: -offset * 1000.0,
+            bpms,
+            stops,
+        })
+    }
+
+    fn get_bpm(&self, beat: f64) -> f64 {
+        self.bpms
+            .iter()
+            .take_while(|(b, _)| *b <= beat)
+            .last()
+            .unwrap_or(&self.bpms[0])
+            .1
+    }
+
+    /// Get the time in ms of the beat, the note at the stop beat is before the stop.
+    fn get_time(&self, beat: f64) -> f64 {
+        let mut time = self.start_time;
+        let mut last_beat = 0.0;
+        let mut bpm = self.get_bpm(0.0);
+        for &(b, new_bpm) in self.bpms.iter().skip_while(|(b, _)| *b <= 0.0) {
+            if b > beat {
+                break;
+            }
+            time += (b - last_beat) * 60_000.0 / bpm;
+            last_beat = b;
+            bpm = new_bpm;
+        }
+        time += (beat - last_beat) * 60_000.0 / bpm;
+        time + self
+            .stops
+            .iter()
+            .take_while(|(b, _)| *b < beat)
+            .map(|(_, secs)| secs * 1000.0)
+            .sum::<f64>()
+    }
+
+    fn get_stop_ms(&self, beat: f64) -> f64 {
+        self.stops
+            .iter()
+            .filter(|(b, _)| *b == beat)
+            .map(|(_, secs)| secs * 1000.0)
+            .sum()
+    }
+
+    fn to_timings(&self) -> Vec<Timing> {
+        let time_signature = NonZeroU8::new(BEATS_PER_MEASURE as u8).unwrap();
+        let mut beats = self
+            .bpms
+            .iter()
+            .chain(self.stops.iter())
+            .map(|(beat, _)| beat.max(0.0))
+            .collect::<Vec<_>>();
+        beats.sort_by(f64::total_cmp);
+        beats.dedup();
+
+        // (time, timing), the time may be negative.
+        let mut timings: Vec<(f64, Timing)> = vec![];
+        for beat in beats {
+            let time = self.get_time(beat);
+            let bpm = self.get_bpm(beat);
+            let stop = self.get_stop_ms(beat);
+            if stop > 0.0 {
+                timings.push((
+                    time,
+                    Timing::new_speed(0.0, time.round() as OffsetType, time_signature),
+                ));
+            }
+            let time = time + stop;
+            let mut timing =
+                Timing::new(Bpm::from(bpm), time.round() as OffsetType, time_signature);
+            timing.set_speed = Some(1.0);
+            timings.push((time, timing));
+        }
+
+        // our timing cannot start before 0, move the last one before 0 by whole beats.
+        let first = timings.iter().position(|(time, _)| *time >= 0.0);
+        let first = first.unwrap_or(timings.len());
+        if first > 0 {
+            let next_time = timings.get(first).map(|(time, _)| *time);
+            let (mut time, mut timing) = timings.remove(first - 1);
+            timings.drain(..first - 1);
+            if let Some(bpm) = timing.set_bpm {
+                let bpm: f32 = bpm.into();
+                let beat_len = 60_000.0 / bpm as f64;
+                time += (-time / beat_len).ceil() * beat_len;
+            } else {
+                // stopping at 0.
+                time = 0.0;
+            }
+            if next_time.map(|x| time < x).unwrap_or(true) {
+                timing.offset = time.round() as OffsetType;
+                timings.insert(0, (time, timing));
+            }
+        }
+
+        let mut result: Vec<Timing> = vec![];
+        for (_, timing) in timings {
+            result.retain(|x| x.offset != timing.offset);
+            result.push(timing);
+        }
+        result
+    }
+}
+
+fn convert_chart(
+    chart: &SmChart,
+    song_tags: &SongTags,
+    beatmap: &mut SongBeatmapFile,
+) -> anyhow::Result<()> {
+    let timing = SmTiming::new(
+        chart.offset.as_deref().unwrap_or(&song_tags.offset),
+        chart.bpms.as_deref().unwrap_or(&song_tags.bpms),
+        chart.stops.as_deref().unwrap_or(&song_tags.stops),
+    )?;
+    beatmap.timing_group.timing_lines[0].timings = timing.to_timings();
+
+    let keys = FOUR_KEY_X.len();
+    let note_width = 1.0 / keys as f32;
+    let mut holding: [Option<f64>; 4] = [None; 4];
+    for (measure_idx, measure) in chart.notes.split(',').enumerate() {
+        let rows = measure
+            .lines()
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>();
+        for (row_idx, row) in rows.iter().enumerate() {
+            if row.chars().count() != keys {
+                return Err(anyhow!(
+                    "Invalid note row {} in measure {}",
+                    row,
+                    measure_idx
+                ));
+            }
+            let beat =
+                (measure_idx as f64 + row_idx as f64 / rows.len() as f64) * BEATS_PER_MEASURE;
+            for (column, c) in row.chars().enumerate() {
+                let x = FOUR_KEY_X[column];
+                match c {
+                    '1' => beatmap.normal_notes.push(NormalNote {
+                        x,
+                        width: note_width,
+                        time: timing.get_time(beat).round() as OffsetType,
+                        note_type: NoteHitType::Click,
+                        timing_group: 0,
+                    }),
+                    // hold and roll head
+                    '2' | '4' => holding[column] = Some(beat),
+                    '3' => {
+                        let start = holding[column]
+                            .take()
+                            .ok_or(anyhow!("Hold tail without head in measure {}", measure_idx))?;
+                        beatmap.long_notes.push(LongNote {
+                            x,
+                            width: note_width,
+                            start_time: timing.get_time(start).round() as OffsetType,
+                            end_time: timing.get_time(beat).round() as OffsetType,
+                            timing_group: 0,
+                        });
+                    }
+                    // empty, mine, lift, fake and keysound
+                    _ => {}
+                }
+            }
+        }
+    }
+    if holding.iter().any(Option::is_some) {
+        return Err(anyhow!("Hold head without tail"));
+    }
+    // the music starts at 0, the notes before cannot be played.
+    let first = beatmap
+        .normal_notes
+        .iter()
+        .map(|x| x.time)
+        .chain(beatmap.long_notes.iter().map(|x| x.start_time))
+        .min();
+    if let Some(time) = first.filter(|x| *x < 0) {
+        return Err(anyhow!("The note at {}ms is before the music starts", time));
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct SongTags {
+    title: String,
+    artist: String,
+    credit: String,
+    music: String,
+    sample_start: Option<OffsetType>,
+    sample_length: Option<OffsetType>,
+    background: String,
+    offset: String,
+    bpms: String,
+    stops: String,
+}
+
+/// Parse the non-negative secs into ms.
+fn parse_secs(value: &str) -> Option<OffsetType> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|x| *x >= 0.0)
+        .map(|x| (x * 1000.0).round() as OffsetType)
+}
+
+/// Parse the stepmania chart, every `dance-single` difficulty will be converted.
+pub fn parse_sm(data: &str) -> anyhow::Result<StepManiaSong> {
+    let mut song_tags = SongTags::default();
+    let mut charts: Vec<SmChart> = vec![];
+    // in ssc, the chart tags follow the `#NOTEDATA`.
+    let mut in_ssc_chart = false;
+
+    for (tag, value) in parse_tags(data) {
+        if in_ssc_chart {
+            let chart = charts.last_mut().unwrap();
+            match tag.as_str() {
+                "STEPSTYPE" => chart.steps_type = value,
+                "DESCRIPTION" => chart.description = value,
+                "DIFFICULTY" => chart.difficulty = value,
+                "CREDIT" => chart.credit = value,
+                "NOTES" | "NOTES2" => chart.notes = value,
+                "OFFSET" => chart.offset = Some(value),
+                "BPMS" => chart.bpms = Some(value),
+                "STOPS" => chart.stops = Some(value),
+                "NOTEDATA" => charts.push(SmChart::default()),
+                _ => {}
+            }
+            continue;
+        }
+        match tag.as_str() {
+            "TITLE" => song_tags.title = value,
+            "ARTIST" => song_tags.artist = value,
+            "CREDIT" => song_tags.credit = value,
+            "MUSIC" => song_tags.music = value,
+            "SAMPLESTART" => song_tags.sample_start = parse_secs(&value),
+            "SAMPLELENGTH" => song_tags.sample_length = parse_secs(&value),
+            "BACKGROUND" => song_tags.background = value,
+            "OFFSET" => song_tags.offset = value,
+            "BPMS" => song_tags.bpms = value,
+            "STOPS" | "FREEZES" => song_tags.stops = value,
+            "NOTEDATA" => {
+                in_ssc_chart = true;
+                charts.push(SmChart::default());
+            }
+            "NOTES" => {
+                // type:description:difficulty:meter:radar:notes
+                let fields = value.splitn(6, ':').map(str::trim).collect::<Vec<_>>();
+                if fields.len() != 6 {
+                    return Err(anyhow!("Invalid notes with {} fields", fields.len()));
+                }
+                charts.push(SmChart {
+                    steps_type: fields[0].to_string(),
+                    description: fields[1].to_string(),
+                    difficulty: fields[2].to_string(),
+                    notes: fields[5].to_string(),
+                    ..Default::default()
+                });
+            }
+            _ => {}
+        }
+    }
+
+    if song_tags.music.is_empty() {
+        return Err(anyhow!("No music file found"));
+    }
+
+    let mut result = vec![];
+    for chart in charts
+        .iter()
+        .filter(|x| x.steps_type.eq_ignore_ascii_case(SM_STEPS_TYPE))
+    {
+        let mut beatmap = SongBeatmapFile::new(song_tags.title.clone());
+        beatmap.rule = MapRule::FourKey;
+        let metadata = &mut beatmap.metadata;
+        metadata.artist = song_tags.artist.clone();
+        metadata.creator = [&chart.credit, &chart.description, &song_tags.credit]
+            .into_iter()
+            .find(|x| !x.is_empty())
+            .cloned()
+            .unwrap_or_default();
+        metadata.version = if chart.difficulty.is_empty() {
+            "Edit".to_string()
+        } else {
+            chart.difficulty.clone()
+        };
+        // edit charts may share the same difficulty.
+        let base_version = metadata.version.clone();
+        let mut idx = 1;
+        while result
+            .iter()
+            .any(|x: &SongBeatmapFile| x.metadata.version == beatmap.metadata.version)
+        {
+            idx += 1;
+            beatmap.metadata.version = format!("{} {}", base_version, idx);
+        }
+
+        convert_chart(chart, &song_tags, &mut beatmap)?;
+        beatmap.update();
+        result.push(beatmap);
+    }
+
+    if result.is_empty() {
+        return Err(anyhow!("No {} chart found", SM_STEPS_TYPE));
+    }
+
+    Ok(StepManiaSong {
+        music: song_tags.music,
+        sample_start: song_tags.sample_start,
+        sample_length: song_tags.sample_length,
+        background: Some(song_tags.background).filter(|x| !x.is_empty()),
+        charts: result,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::beatmap::FOUR_KEY_X;
+    use crate::game::beatmap::sm::parse_sm;
+
+    const SM_CHART: &str = "#TITLE:Song;
+#ARTIST:Someone;
+#MUSIC:song.ogg;
+#SAMPLESTART:12.5;
+#SAMPLELENGTH:10;
+#BACKGROUND:bg.png;
+#OFFSET:0.25;
+#BPMS:0.000=120.000,8.000=240.000;
+#STOPS:4.000=0.500;
+#NOTES:
+     dance-single:
+     Mapper:
+     Hard:
+     9:
+     0,0,0,0,0:
+0000
+0200
+0000
+0000
+,  // measure 2
+0001
+0000
+0300
+M000
+,
+4000
+3000
+;
+#NOTES:
+     dance-double:
+     :
+     Hard:
+     9:
+     0,0,0,0,0:
+00000000
+;
+#NOTES:
+     dance-single:
+     :
+     Easy:
+     3:
+     0,0,0,0,0:
+0000
+0010
+;
+";
+
+    #[test]
+    fn test_parse_sm() {
+        let song = parse_sm(SM_CHART).unwrap();
+        assert_eq!(song.music, "song.ogg");
+        assert_eq!(song.sample_start, Some(12500));
+        assert_eq!(song.sample_length, Some(10000));
+        assert_eq!(song.background.as_deref(), Some("bg.png"));
+        assert_eq!(song.charts.len(), 2);
+        assert_eq!(song.charts[1].metadata.version, "Easy");
+
+        let beatmap = &song.charts[0];
+        assert_eq!(beatmap.metadata.title, "Song");
+        assert_eq!(beatmap.metadata.creator, "Mapper");
+        assert_eq!(beatmap.metadata.version, "Hard");
+
+        let timings = &beatmap.timing_group.timing_lines[0].timings;
+        // beat 0 at -250ms moved to 250ms, stop at 1750ms, resume at 2250ms, 240 bpm at 4250ms
+        let offsets = timings.iter().map(|x| x.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![250, 1750, 2250, 4250]);
+        assert_eq!(timings[1].set_speed, Some(0.0));
+        assert_eq!(timings[2].get_bpm().to_string(), "120.00");
+        assert_eq!(timings[3].get_bpm().to_string(), "240.00");
+
+        assert_eq!(beatmap.normal_notes.len(), 1);
+        // the note at the stop beat is before the stop
+        assert_eq!(beatmap.normal_notes[0].time, 1750);
+        assert_eq!(beatmap.normal_notes[0].x, FOUR_KEY_X[3]);
+
+        assert_eq!(beatmap.long_notes.len(), 2);
+        assert_eq!(beatmap.long_notes[0].x, FOUR_KEY_X[1]);
+        assert_eq!(beatmap.long_notes[0].start_time, 250);
+        assert_eq!(beatmap.long_notes[0].end_time, 3250);
+        // roll after the bpm change
+        assert_eq!(beatmap.long_notes[1].start_time, 4250);
+        assert_eq!(beatmap.long_notes[1].end_time, 4750);
+
+        // beat 0 is at -250ms, the note there is rejected.
+        assert!(parse_sm(&SM_CHART.replacen("0000\n0200", "1000\n0200", 1)).is_err());
+    }
+
+    #[test]
+    fn test_parse_ssc() {
+        let data = "#VERSION:0.83;
+#TITLE:Song;
+#MUSIC:song.mp3;
+#OFFSET:0;
+#BPMS:0=60;
+#NOTEDATA:;
+#STEPSTYPE:dance-single;
+#DIFFICULTY:Challenge;
+#CREDIT:Mapper;
+#BPMS:0=120;
+#NOTES:
+1000
+0100
+0010
+0001
+;
+#NOTEDATA:;
+#STEPSTYPE:dance-single;
+#DIFFICULTY:Edit;
+#NOTES:
+1000
+;
+#NOTEDATA:;
+#STEPSTYPE:dance-single;
+#DIFFICULTY:Edit;
+#NOTES:
+0001
+;
+";
+        let song = parse_sm(data).unwrap();
+        assert_eq!(song.charts.len(), 3);
+        let beatmap = &song.charts[0];
+        assert_eq!(beatmap.metadata.version, "Challenge");
+        assert_eq!(beatmap.metadata.creator, "Mapper");
+        let times = beatmap
+            .normal_notes
+            .iter()
+            .map(|x| x.time)
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![0, 500, 1000, 1500]);
+        // song timing
+        assert_eq!(
+            song.charts[1].timing_group.timing_lines[0].timings[0]
+                .get_bpm()
+                .to_string(),
+            "60.00"
+        );
+        assert_eq!(song.charts[1].metadata.version, "Edit");
+        assert_eq!(song.charts[2].metadata.version, "Edit 2");
+    }
+}
@@ -0,0 +1,113 @@
+//! The summary numbers of the beatmap.
+
+use crate::game::OffsetType;
+use crate::game::beatmap::difficulty::calculate_difficulty;
+use crate::game::beatmap::file::SongBeatmapFile;
+use crate::game::note::NoteExt;
+use crate::game::timing::Bpm;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BeatmapStats {
+    pub normal_notes: usize,
+    pub long_notes: usize,
+    /// The first note time, none if no notes.
+    pub first_time: Option<OffsetType>,
+    /// The last note (end) time, none if no notes.
+    pub last_time: Option<OffsetType>,
+    pub min_bpm: Bpm,
+    pub max_bpm: Bpm,
+    pub rating: f32,
+}
+
+impl BeatmapStats {
+    pub fn new(beatmap: &SongBeatmapFile) -> Self {
+        Self::with_rating(beatmap, calculate_difficulty(beatmap).rating)
+    }
+
+    /// Create the stats with the calculated rating.
+    pub fn with_rating(beatmap: &SongBeatmapFile, rating: f32) -> Self {
+        let times = beatmap.normal_notes.iter().map(|x| (x.time, x.time)).chain(
+            beatmap
+                .long_notes
+                .iter()
+                .map(|x| (x.start_time, x.get_end_time_or_time())),
+        );
+        let first_time = times.clone().map(|x| x.0).min();
+        let last_time = times.map(|x| x.1).max();
+
+        let mut bpms = beatmap
+            .timing_group
+            .timing_lines
+            .iter()
+            .flat_map(|x| x.timings.iter())
+            .filter_map(|x| x.set_bpm)
+            .collect::<Vec<_>>();
+        bpms.sort_by(|a, b| Into::<f32>::into(*a).total_cmp(&(*b).into()));
+
+        Self {
+            normal_notes: beatmap.normal_notes.len(),
+            long_notes: beatmap.long_notes.len(),
+            first_time,
+            last_time,
+            min_bpm: bpms.first().copied().unwrap_or_default(),
+            max_bpm: bpms.last().copied().unwrap_or_default(),
+            rating,
+        }
+    }
+
+    pub fn get_note_count(&self) -> usize {
+        self.normal_notes + self.long_notes
+    }
+
+    /// The time from the first note to the last note end.
+    pub fn get_duration(&self) -> OffsetType {
+        match (self.first_time, self.last_time) {
+            (Some(first), Some(last)) => last - first,
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::beatmap::file::SongBeatmapFile;
+    use crate::game::beatmap::stats::BeatmapStats;
+    use crate::game::note::{LongNote, NormalNote, NoteHitType};
+    use crate::game::timing::{Bpm, Timing};
+    use std::num::NonZeroU8;
+
+    #[test]
+    fn test_stats() {
+        let mut beatmap = SongBeatmapFile::new("Song".into());
+        let time_signature = NonZeroU8::new(4).unwrap();
+        beatmap.timing_group.timing_lines[0].timings = vec![
+            Timing::new(Bpm::from(180.0), 0, time_signature),
+            Timing::new(Bpm::from(90.0), 1000, time_signature),
+            Timing::new_speed(2.0, 1500, time_signature),
+        ];
+        beatmap.normal_notes.push(NormalNote {
+            x: 0.0,
+            width: 0.25,
+            time: 500,
+            note_type: NoteHitType::Click,
+            timing_group: 0,
+        });
+        beatmap.long_notes.push(LongNote {
+            x: 0.0,
+            width: 0.25,
+            start_time: 1000,
+            end_time: 3000,
+            timing_group: 0,
+        });
+
+        let stats = BeatmapStats::new(&beatmap);
+        assert_eq!(stats.get_note_count(), 2);
+        assert_eq!(stats.first_time, Some(500));
+        assert_eq!(stats.last_time, Some(3000));
+        assert_eq!(stats.get_duration(), 2500);
+        assert_eq!(stats.min_bpm.to_string(), "90.00");
+        assert_eq!(stats.max_bpm.to_string(), "180.00");
+        assert!(stats.rating > 0.0);
+    }
+}
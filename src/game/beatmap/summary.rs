@@ -1,61 +1,88 @@
-use crate::game::beatmap::play::Gaming;
+use crate::game::beatmap::mods::PlayMods;
+use crate::game::beatmap::play::{Gaming, JudgeTimes};
 use crate::game::OffsetType;
 
+/// The hit delays counted in the bins covering the miss window, the bin width follows the window.
+#[derive(Debug, PartialEq)]
 pub struct HitSummary {
-    /// Summary  [-305, -295] ... (-15, -5] (-5, 5) [5, 15) ... [295, 305]
+    /// Summary  [-205, -195] ... (-15, -5] (-5, 5) [5, 15) ... [195, 205] for the default window
     pub delay_count: Vec<u32>,
     pub mx: u32,
+    /// The width of the bin.
+    pub step: OffsetType,
 }
 
+#[derive(Debug, PartialEq)]
 pub struct BeatmapPlayResult {
+    /// The score without the mods multiplier.
     pub score: u32,
+    pub mods: PlayMods,
+    pub multiplier: f32,
+    /// The score with the mods multiplier.
+    pub mod_score: u32,
+    /// The health ran out before the end.
+    pub failed: bool,
+    /// The health after every judgement with the time.
+    pub health_history: Vec<(OffsetType, f32)>,
+    /// The time of the last note end.
+    pub end_time: OffsetType,
     pub hit_summary: HitSummary,
 }
 
 impl HitSummary {
+    /// The bin count of one side.
+    const SIDE_BINS: OffsetType = 20;
+
+    pub fn new(judge: &JudgeTimes, deltas: &[OffsetType]) -> Self {
+        let step = (judge.miss / Self::SIDE_BINS).max(1);
+        let side = (judge.miss + step / 2) / step;
+        let mut delay_count = vec![0; side as usize * 2 + 1];
+        for x in deltas {
+            // round half away from zero, so the bins are symmetric.
+            let idx = (*x as f64 / step as f64).round() as OffsetType + side;
+            delay_count[idx.clamp(0, side * 2) as usize] += 1;
+        }
+        let mx = delay_count.iter().copied().max().unwrap_or(0).max(1);
+        Self {
+            delay_count,
+            mx,
+            step,
+        }
+    }
+
     #[inline]
-    pub const fn start_offset() -> OffsetType {
-        -205
+    pub fn start_offset(&self) -> OffsetType {
+        -(self.delay_count.len() as OffsetType / 2 * self.step) - self.step / 2
     }
+
+    /// The bounds of the bins, one more than the bins.
     #[inline]
-    pub fn bottom_numbers() -> impl Iterator<Item = OffsetType> {
-        (0..).map(|i| i * 10 + Self::start_offset()).take_while(|x| *x <= 205)
+    pub fn bottom_numbers(&self) -> impl Iterator<Item = OffsetType> + use<> {
+        let start = self.start_offset();
+        let step = self.step;
+        (0..=self.delay_count.len() as OffsetType).map(move |i| i * step + start)
     }
 }
 
 impl BeatmapPlayResult {
     pub fn from_game(game: &Gaming) -> Self {
         let score = &game.score_counter;
-        let mut delay_count = vec![];
-        let mut start_time_map_idx = vec![];
-        for left in HitSummary::bottom_numbers() {
-            delay_count.push(0);
-            if left <= -5 {
-                start_time_map_idx.push(left + 1);
-            } else {
-                start_time_map_idx.push(left);
-            }
-        }
-        
-        // we pop the last, for we consider range
-        delay_count.pop();
-        start_time_map_idx.pop();
-        debug_assert!(!delay_count.is_empty());
-        let mut mx = 1;
-        for x in score.get_deltas() {
-            match start_time_map_idx.binary_search(x) {
-                Ok(idx) => {
-                    delay_count[idx] += 1;
-                }
-                Err(idx) => {
-                    delay_count[idx.saturating_sub(1)] += 1;
-                }
-            }
-        }
-        mx = mx.max(*delay_count.iter().max().unwrap_or(&0));
         Self {
             score: score.get_score(),
-            hit_summary: HitSummary { delay_count, mx },
+            mods: *score.get_mods(),
+            multiplier: score.get_multiplier(),
+            mod_score: score.get_mod_score(),
+            failed: game.health.is_failed(),
+            health_history: game.health.get_history().to_vec(),
+            end_time: game
+                .raw_file
+                .normal_notes
+                .iter()
+                .map(|x| x.time)
+                .chain(game.raw_file.long_notes.iter().map(|x| x.end_time))
+                .max()
+                .unwrap_or(0),
+            hit_summary: HitSummary::new(game.get_judge_times(), score.get_deltas()),
         }
     }
 }
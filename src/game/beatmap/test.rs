@@ -4,7 +4,36 @@ use crate::game::timing::{get_ron_options, Timing};
 use ron::extensions::Extensions;
 use ron::Options;
 use serde::{Deserialize, Serialize};
-use crate::game::beatmap::file::de_from_ron;
+use crate::game::beatmap::file::{de_from_ron, ser_to_ron, SongBeatmapFile, BEATMAP_FILE_VERSION};
+use crate::game::beatmap::migration::get_file_version;
+use crate::game::beatmap::play::{JudgePreset, JudgeTimes, DEFAULT_JUDGE_LEVEL};
+use crate::game::beatmap::summary::HitSummary;
+use ron::ser::PrettyConfig;
+use crate::game::beatmap::{get_lane_note_width, get_lanes_x};
+use crate::game::note::{LongNote, NormalNote, NoteHitType};
+use crate::game::OffsetType;
+
+/// The 4K note in the lane.
+pub(crate) fn lane_note(lane: usize, time: OffsetType, note_type: NoteHitType) -> NormalNote {
+    NormalNote {
+        x: get_lanes_x(4)[lane],
+        width: get_lane_note_width(4),
+        time,
+        note_type,
+        timing_group: 0,
+    }
+}
+
+/// The 4K long note in the lane.
+pub(crate) fn lane_long_note(lane: usize, start_time: OffsetType, end_time: OffsetType) -> LongNote {
+    LongNote {
+        x: get_lanes_x(4)[lane],
+        width: get_lane_note_width(4),
+        start_time,
+        end_time,
+        timing_group: 0,
+    }
+}
 
 fn check_timing_eq(a: &Timing, b: &Timing) {
     assert_eq!(a.set_bpm, b.set_bpm);
@@ -41,3 +70,107 @@ fn test_ser() {
         result
     );
 }
+
+fn ser_beatmap_with_version(version: u8) -> Vec<u8> {
+    let mut beatmap = SongBeatmapFile::new("Song".into());
+    beatmap.metadata.version = "Hard".into();
+    beatmap.version = version;
+    let mut data = vec![];
+    ser_to_ron(&beatmap, &mut data, Some(PrettyConfig::default())).unwrap();
+    data
+}
+
+#[test]
+fn test_migrate() {
+    let beatmap = SongBeatmapFile::load_from_bytes(&ser_beatmap_with_version(0)).unwrap();
+    assert_eq!(beatmap.version, BEATMAP_FILE_VERSION);
+    assert_eq!(beatmap.metadata.version, "Hard");
+
+    // the version 1 file has no judge level.
+    let data = String::from_utf8(ser_beatmap_with_version(1)).unwrap();
+    let data = data.replace("judge_level: None,", "");
+    let beatmap = SongBeatmapFile::load_from_bytes(data.as_bytes()).unwrap();
+    assert_eq!(beatmap.version, BEATMAP_FILE_VERSION);
+    assert_eq!(beatmap.judge_level, None);
+
+    // the version 2 file has no drain level.
+    let data = String::from_utf8(ser_beatmap_with_version(2)).unwrap();
+    let data = data.replace("drain_level: None,", "");
+    let beatmap = SongBeatmapFile::load_from_bytes(data.as_bytes()).unwrap();
+    assert_eq!(beatmap.version, BEATMAP_FILE_VERSION);
+    assert_eq!(beatmap.drain_level, None);
+
+    let beatmap = SongBeatmapFile::load_from_bytes(&ser_beatmap_with_version(3)).unwrap();
+    assert_eq!(beatmap.version, BEATMAP_FILE_VERSION);
+
+    let beatmap = SongBeatmapFile::load_from_bytes(
+        &ser_beatmap_with_version(BEATMAP_FILE_VERSION),
+    )
+    .unwrap();
+    assert_eq!(beatmap.version, BEATMAP_FILE_VERSION);
+
+    let err = SongBeatmapFile::load_from_bytes(&ser_beatmap_with_version(
+        BEATMAP_FILE_VERSION + 1,
+    ))
+    .unwrap_err();
+    assert!(err.to_string().contains("newer"));
+}
+
+#[test]
+fn test_save_current_version() {
+    let path = std::env::temp_dir().join("rr_test_save_current_version.rr");
+    let mut beatmap = SongBeatmapFile::new("Song".into());
+    beatmap.version = 0;
+    beatmap.save_to(&path).unwrap();
+    let data = std::fs::read(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(get_file_version(&data).unwrap(), BEATMAP_FILE_VERSION);
+}
+
+#[test]
+fn test_judge_times() {
+    assert_eq!(JudgeTimes::from_level(DEFAULT_JUDGE_LEVEL, 1.0), JudgeTimes::default());
+    let hard = JudgeTimes::from_level(JudgePreset::Expert.get_level(), 1.0);
+    assert_eq!((hard.perfect, hard.miss), (18, 120));
+    let fast = JudgeTimes::from_level(DEFAULT_JUDGE_LEVEL, 1.5);
+    assert_eq!((fast.perfect, fast.miss), (45, 300));
+    assert_eq!(JudgePreset::from_name("Hard"), Some(JudgePreset::Hard));
+
+    let summary = HitSummary::new(&JudgeTimes::default(), &[-200, -5, 0, 4, 5, 200]);
+    assert_eq!(summary.start_offset(), -205);
+    assert_eq!(summary.delay_count.len(), 41);
+    assert_eq!(summary.bottom_numbers().last(), Some(205));
+    assert_eq!(summary.delay_count[0], 1);
+    assert_eq!(&summary.delay_count[19..22], &[1, 2, 1]);
+    assert_eq!(summary.delay_count[40], 1);
+
+    let summary = HitSummary::new(&hard, &[-120, 0, 120]);
+    assert_eq!(summary.step, 6);
+    assert_eq!(summary.bottom_numbers().next(), Some(-123));
+    assert_eq!(summary.bottom_numbers().last(), Some(123));
+    assert_eq!((summary.delay_count[0], summary.delay_count[40]), (1, 1));
+}
+
+#[test]
+fn test_lanes() {
+    use crate::game::beatmap::{get_lane, get_lanes_x, MapRule, FOUR_KEY_X, MAX_KEYS, MIN_KEYS};
+    assert_eq!(get_lanes_x(4), FOUR_KEY_X);
+    for keys in MIN_KEYS..=MAX_KEYS {
+        let lanes = get_lanes_x(keys);
+        assert_eq!(lanes.len(), keys as usize);
+        for (lane, x) in lanes.iter().enumerate() {
+            assert_eq!(get_lane(keys, *x), lane);
+        }
+        assert_eq!(get_lane(keys, -1.5), 0);
+        assert_eq!(get_lane(keys, 1.0), keys as usize - 1);
+        assert_eq!(MapRule::from_keys(keys).get_keys(), Some(keys));
+    }
+    assert_eq!(MapRule::from_keys(4), MapRule::FourKey);
+    assert_eq!(MapRule::Falling.get_keys(), None);
+
+    // the key count out of range is rejected when loaded.
+    assert_eq!(de_from_ron::<MapRule>(b"Keys(7)").unwrap(), MapRule::Keys(7));
+    assert_eq!(de_from_ron::<MapRule>(b"Keys(4)").unwrap(), MapRule::FourKey);
+    assert!(de_from_ron::<MapRule>(b"Keys(0)").is_err());
+    assert!(de_from_ron::<MapRule>(b"Keys(11)").is_err());
+}
@@ -0,0 +1,173 @@
+//! The keys of the lanes for every key count.
+//!
+//! The binding is stored in the config as the key names, such as `key_binding_4k = ["KeyD", ...]`.
+
+use crate::engine::config::Config;
+use winit::keyboard::KeyCode;
+
+/// The keys can be bound to the lanes.
+pub static BINDABLE_KEYS: &[KeyCode] = &[
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+    KeyCode::Space,
+    KeyCode::Semicolon,
+    KeyCode::Quote,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::Backslash,
+    KeyCode::BracketLeft,
+    KeyCode::BracketRight,
+    KeyCode::Minus,
+    KeyCode::Equal,
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::ControlLeft,
+    KeyCode::ControlRight,
+    KeyCode::AltLeft,
+    KeyCode::AltRight,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowRight,
+];
+
+pub fn get_binding_cfg_key(keys: u8) -> String {
+    format!("key_binding_{}k", keys)
+}
+
+/// The name stored in the config.
+pub fn get_key_name(code: KeyCode) -> String {
+    format!("{:?}", code)
+}
+
+/// The name shown to the player, such as `D` for `KeyD`.
+pub fn get_key_label(code: KeyCode) -> String {
+    let name = get_key_name(code);
+    ["Key", "Digit"]
+        .iter()
+        .find_map(|x| name.strip_prefix(x))
+        .unwrap_or(&name)
+        .to_string()
+}
+
+pub fn parse_key_name(name: &str) -> Option<KeyCode> {
+    BINDABLE_KEYS
+        .iter()
+        .find(|x| get_key_name(**x) == name)
+        .copied()
+}
+
+/// The home row keys, the space is in the middle for the odd key count.
+pub fn get_default_binding(keys: u8) -> Vec<KeyCode> {
+    use KeyCode::*;
+    let hands: &[KeyCode] = match keys {
+        4 | 5 => &[KeyD, KeyF, KeyJ, KeyK],
+        6 | 7 => &[KeyS, KeyD, KeyF, KeyJ, KeyK, KeyL],
+        10 => &[KeyA, KeyS, KeyD, KeyF, KeyV, KeyN, KeyJ, KeyK, KeyL, Semicolon],
+        _ => &[KeyA, KeyS, KeyD, KeyF, KeyJ, KeyK, KeyL, Semicolon],
+    };
+    let mut binding = hands.to_vec();
+    if keys & 1 == 1 {
+        binding.insert(binding.len() / 2, Space);
+    }
+    binding
+}
+
+/// Load the binding of the key count, the default is used if the config is invalid.
+pub fn load_binding(cfg: &mut Config, keys: u8) -> Vec<KeyCode> {
+    let default = get_default_binding(keys);
+    let names = default.iter().map(|x| get_key_name(*x)).collect::<Vec<_>>();
+    let binding = cfg
+        .get_str_array_def(&get_binding_cfg_key(keys), &names)
+        .iter()
+        .map(|x| parse_key_name(x))
+        .collect::<Option<Vec<_>>>();
+    match binding {
+        Some(binding) if binding.len() == keys as usize => binding,
+        _ => {
+            log::warn!("Invalid key binding for {}K, use the default", keys);
+            default
+        }
+    }
+}
+
+pub fn save_binding(cfg: &mut Config, keys: u8, binding: &[KeyCode]) {
+    let names = binding.iter().map(|x| get_key_name(*x)).collect::<Vec<_>>();
+    cfg.set_str_array(&get_binding_cfg_key(keys), &names);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::config::Config;
+    use crate::game::beatmap::{MAX_KEYS, MIN_KEYS};
+    use crate::game::key_binding::*;
+    use std::collections::HashSet;
+    use winit::keyboard::KeyCode;
+
+    #[test]
+    fn test_key_binding() {
+        for code in BINDABLE_KEYS {
+            assert_eq!(parse_key_name(&get_key_name(*code)), Some(*code));
+        }
+        assert_eq!(get_key_label(KeyCode::KeyD), "D");
+        assert_eq!(get_key_label(KeyCode::Digit1), "1");
+        assert_eq!(get_key_label(KeyCode::Space), "Space");
+
+        let mut cfg = Config::load("key_binding_5k = [\"KeyD\", \"Escape\"]").unwrap();
+        for keys in MIN_KEYS..=MAX_KEYS {
+            let binding = get_default_binding(keys);
+            assert_eq!(binding.len(), keys as usize);
+            assert_eq!(binding.iter().collect::<HashSet<_>>().len(), keys as usize);
+            assert_eq!(load_binding(&mut cfg, keys), binding);
+        }
+
+        let binding = [KeyCode::KeyA, KeyCode::KeyS, KeyCode::KeyK, KeyCode::KeyL];
+        save_binding(&mut cfg, 4, &binding);
+        assert_eq!(load_binding(&mut cfg, 4), binding);
+    }
+}
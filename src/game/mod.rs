@@ -12,10 +12,17 @@ pub type OffsetType = MsType;
 pub type GameTimeType = f64;
 
 pub mod note;
+pub mod package;
+pub(crate) mod preview;
+pub mod replay;
+pub mod simulate;
 pub mod song;
+pub mod song_index;
+pub mod song_watcher;
 pub mod beatmap;
+pub(crate) mod key_binding;
 pub mod timing;
-pub mod render;
+pub(crate) mod render;
 
 #[inline]
 #[must_use]
@@ -29,6 +36,11 @@ pub fn offset_type_to_secs(offset: OffsetType) -> GameTimeType {
     offset as GameTimeType / 1000.0
 }
 
+/// Map the screen x into the game x, the play rect is from -1 to 1.
+pub fn map_screen_x_to_game_x(play_rect: &Rect, x: f32) -> f32 {
+    (x - play_rect.center().x) * 2.0 / play_rect.width()
+}
+
 pub fn get_play_rect(rect: Rect) -> Rect {
     let center_point = rect.center();
     // 4:3 play area.
@@ -0,0 +1,370 @@
+//! The song package (`.rrz`) to share the whole song.
+//!
+//! The package is a zip archive in the same layout as the song dir: the `bgm.*`, every `*.rr`
+//! beatmap and any other assets, with a [`PackageManifest`] named [`PACKAGE_MANIFEST`] at the root.
+
+use crate::game::beatmap::file::{de_from_ron, ser_to_ron, SongBeatmapFile};
+use crate::game::beatmap::BEATMAP_EXT;
+use crate::game::song::{probe_audio, sanitize_file_name, SongInfo};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+pub const PACKAGE_EXT: &'static str = "rrz";
+pub const PACKAGE_MANIFEST: &'static str = "package.ron";
+const PACKAGE_VERSION: u8 = 0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageManifest {
+    pub version: u8,
+    /// The song title, also the song dir name.
+    pub title: String,
+}
+
+/// What to do if the song dir already exists when importing.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PackageConflict {
+    /// Import into a new dir named `title (n)`.
+    Rename,
+    /// Import into the existing dir, the files in the package overwrite the existing.
+    Merge,
+}
+
+fn is_bgm_file(path: &Path) -> bool {
+    path.parent() == Some(Path::new(""))
+        && path.file_stem().map(|x| x == "bgm").unwrap_or(false)
+        && path
+            .extension()
+            .map(|x| {
+                let ext = x.to_string_lossy().to_lowercase();
+                SongInfo::supported_bgm_format().contains(&ext.as_str())
+            })
+            .unwrap_or(false)
+}
+
+/// Collect all the files in the dir recursively, return the path relative to the `root`.
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if path.is_file() {
+            files.push(path.strip_prefix(root)?.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// Write the song dir into the package.
+pub fn write_package(song_dir: &Path, title: &str, writer: impl Write + Seek) -> anyhow::Result<()> {
+    let mut files = vec![];
+    collect_files(song_dir, song_dir, &mut files)?;
+    files.sort();
+
+    let mut zip = ZipWriter::new(writer);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file(PACKAGE_MANIFEST, options)?;
+    let manifest = PackageManifest {
+        version: PACKAGE_VERSION,
+        title: title.to_string(),
+    };
+    ser_to_ron(&manifest, &mut zip, None)?;
+
+    for file in files {
+        let name = file
+            .components()
+            .map(|x| x.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if name == PACKAGE_MANIFEST {
+            continue;
+        }
+        // the audio is compressed already.
+        let options = if is_bgm_file(&file) {
+            options.compression_method(CompressionMethod::Stored)
+        } else {
+            options
+        };
+        zip.start_file(name, options)?;
+        std::io::copy(&mut std::fs::File::open(song_dir.join(&file))?, &mut zip)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Check every entry is inside the package and every beatmap can be loaded.
+///
+/// Return the manifest if the package has.
+fn validate_package<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+) -> anyhow::Result<Option<PackageManifest>> {
+    let mut manifest = None;
+    let mut has_bgm = false;
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        let path = file
+            .enclosed_name()
+            .ok_or(anyhow!("Unsafe path {} in package", file.name()))?;
+        if file.is_dir() {
+            continue;
+        }
+        if path == Path::new(PACKAGE_MANIFEST) {
+            let mut data = vec![];
+            file.read_to_end(&mut data)?;
+            let m: PackageManifest = de_from_ron(&data)?;
+            if m.version > PACKAGE_VERSION {
+                return Err(anyhow!("Unsupported package version {}", m.version));
+            }
+            manifest = Some(m);
+        } else if path.extension().map(|x| x == BEATMAP_EXT).unwrap_or(false) {
+            let mut data = vec![];
+            file.read_to_end(&mut data)?;
+            SongBeatmapFile::load_from_bytes(&data)
+                .map_err(|e| anyhow!("Invalid beatmap {:?} for {:?}", path, e))?;
+        } else if is_bgm_file(&path) {
+            has_bgm = true;
+        }
+    }
+    if !has_bgm {
+        return Err(anyhow!("No bgm found in package"));
+    }
+    Ok(manifest)
+}
+
+fn extract_to<R: Read + Seek>(zip: &mut ZipArchive<R>, song_dir: &Path) -> anyhow::Result<()> {
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        let path = file
+            .enclosed_name()
+            .ok_or(anyhow!("Unsafe path {} in package", file.name()))?;
+        if file.is_dir() || path == Path::new(PACKAGE_MANIFEST) {
+            continue;
+        }
+        let target = song_dir.join(&path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::io::copy(&mut file, &mut std::fs::File::create(&target)?)?;
+    }
+    Ok(())
+}
+
+/// Decode the extracted bgm, the broken audio is not imported.
+fn probe_bgm(dir: &Path) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && is_bgm_file(path.strip_prefix(dir)?) {
+            probe_audio(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Move the extracted files into the song dir, the bgm in the package takes the place.
+fn merge_into(extracted: &Path, song_dir: &Path) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(song_dir)? {
+        let path = entry?.path();
+        if path.is_file() && is_bgm_file(path.strip_prefix(song_dir)?) {
+            std::fs::remove_file(path)?;
+        }
+    }
+    let mut dirs = vec![extracted.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let target = song_dir.join(path.strip_prefix(extracted)?);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            // the temp dir may be on another device.
+            if std::fs::rename(&path, &target).is_err() {
+                std::fs::copy(&path, &target)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The song dir name of the package, the `default_title` is used if the package has no manifest.
+fn get_title(manifest: Option<&PackageManifest>, default_title: &str) -> String {
+    sanitize_file_name(manifest.map(|x| x.title.as_str()).unwrap_or(default_title))
+}
+
+/// Read the song dir name the package is extracted into, without the conflict renaming.
+pub fn read_package_title(reader: impl Read + Seek, default_title: &str) -> anyhow::Result<String> {
+    let mut zip = ZipArchive::new(reader)?;
+    let manifest = match zip.by_name(PACKAGE_MANIFEST) {
+        Ok(mut file) => {
+            let mut data = vec![];
+            file.read_to_end(&mut data)?;
+            Some(de_from_ron::<PackageManifest>(&data)?)
+        }
+        Err(_) => None,
+    };
+    Ok(get_title(manifest.as_ref(), default_title))
+}
+
+/// Extract the package into a song dir under the `root`, return the song dir.
+///
+/// The `default_title` is used if the package has no manifest.
+/// Nothing is written if the package is invalid.
+pub fn extract_package(
+    reader: impl Read + Seek,
+    root: &Path,
+    default_title: &str,
+    conflict: PackageConflict,
+) -> anyhow::Result<PathBuf> {
+    let mut zip = ZipArchive::new(reader)?;
+    let manifest = validate_package(&mut zip)?;
+    let title = get_title(manifest.as_ref(), default_title);
+
+    let mut song_dir = root.join(&title);
+    if song_dir.exists() {
+        match conflict {
+            PackageConflict::Rename => {
+                let mut idx = 2;
+                while song_dir.exists() {
+                    song_dir = root.join(format!("{} ({})", title, idx));
+                    idx += 1;
+                }
+            }
+            PackageConflict::Merge => {
+                // extract aside first, the song keeps its bgm if the extraction fails.
+                let temp_dir = std::env::temp_dir().join(format!(
+                    "rr_package_{}_{}",
+                    std::process::id(),
+                    std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)?
+                        .as_nanos()
+                ));
+                let result = extract_to(&mut zip, &temp_dir)
+                    .and_then(|_| probe_bgm(&temp_dir))
+                    .and_then(|_| merge_into(&temp_dir, &song_dir));
+                let _ = std::fs::remove_dir_all(&temp_dir);
+                return result.map(|_| song_dir);
+            }
+        }
+    }
+    std::fs::create_dir_all(&song_dir)?;
+
+    if let Err(e) = extract_to(&mut zip, &song_dir).and_then(|_| probe_bgm(&song_dir)) {
+        let _ = std::fs::remove_dir_all(&song_dir);
+        return Err(e);
+    }
+    Ok(song_dir)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::beatmap::file::SongBeatmapFile;
+    use crate::game::package::PackageConflict;
+    use crate::game::song::test::{get_test_dir, get_wav};
+    use crate::game::song::{SongInfo, SongManager};
+    use std::io::{Cursor, Write};
+    use std::path::PathBuf;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    fn get_manager(root: PathBuf) -> SongManager {
+        std::fs::create_dir_all(&root).unwrap();
+        SongManager::new(vec![], root)
+    }
+
+    #[test]
+    fn test_export_import() {
+        let dir = get_test_dir("round_trip");
+        let song_dir = dir.join("src").join("Song");
+        std::fs::create_dir_all(song_dir.join("assets")).unwrap();
+        std::fs::write(song_dir.join("bgm.wav"), get_wav()).unwrap();
+        std::fs::write(song_dir.join("assets").join("bg.png"), b"png").unwrap();
+        let mut beatmap = SongBeatmapFile::new("Song".into());
+        beatmap.metadata.version = "Hard".into();
+        beatmap.save_to(&song_dir.join("Song[Hard].rr")).unwrap();
+
+        let manager = get_manager(dir.join("dst"));
+        let info = SongInfo::load(&song_dir).unwrap();
+        let package = dir.join("Song.rrz");
+        manager.export_package(&info, &package).unwrap();
+
+        let info = manager
+            .import_package(&package, PackageConflict::Rename)
+            .unwrap();
+        assert_eq!(info.title, "Song");
+        assert_eq!(info.maps.len(), 1);
+        assert_eq!(info.maps[0].metadata.version, "Hard");
+        let imported = dir.join("dst").join("Song");
+        assert_eq!(
+            std::fs::read(imported.join("assets").join("bg.png")).unwrap(),
+            b"png"
+        );
+        assert!(!imported.join("package.ron").exists());
+
+        let renamed = manager
+            .import_package(&package, PackageConflict::Rename)
+            .unwrap();
+        assert_eq!(renamed.title, "Song (2)");
+
+        let merged = manager
+            .import_package(&package, PackageConflict::Merge)
+            .unwrap();
+        assert_eq!(merged.title, "Song");
+        assert!(info.dirty.load(std::sync::atomic::Ordering::Acquire));
+        assert_eq!(manager.songs.len(), 2);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn write_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        for (name, data) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_reject_invalid() {
+        let dir = get_test_dir("invalid");
+        let manager = get_manager(dir.join("songs"));
+        let packages = [
+            ("bad_map", write_zip(&[("bgm.mp3", b"mp3"), ("a.rr", b"not ron")])),
+            ("no_bgm", write_zip(&[("music.mp3", b"mp3")])),
+            ("slip", write_zip(&[("bgm.mp3", b"mp3"), ("../evil.txt", b"evil")])),
+            ("bad_bgm", write_zip(&[("bgm.wav", b"not really wav")])),
+        ];
+        for (name, data) in packages {
+            let path = dir.join(format!("{}.rrz", name));
+            std::fs::write(&path, data).unwrap();
+            assert!(
+                manager
+                    .import_package(&path, PackageConflict::Rename)
+                    .is_err(),
+                "{} should be rejected",
+                name
+            );
+        }
+        assert!(!dir.join("evil.txt").exists());
+        assert_eq!(std::fs::read_dir(dir.join("songs")).unwrap().count(), 0);
+
+        // the merge failed in extracting keeps the bgm of the song.
+        let song_dir = dir.join("songs").join("broken");
+        std::fs::create_dir_all(&song_dir).unwrap();
+        std::fs::write(song_dir.join("bgm.ogg"), b"ogg").unwrap();
+        let path = dir.join("broken.rrz");
+        std::fs::write(&path, write_zip(&[("bgm.mp3", b"mp3"), ("a", b"a"), ("a/b", b"b")])).unwrap();
+        assert!(manager.import_package(&path, PackageConflict::Merge).is_err());
+        assert!(song_dir.join("bgm.ogg").exists());
+        assert!(!song_dir.join("bgm.mp3").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
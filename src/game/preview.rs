@@ -0,0 +1,179 @@
+//! Play the looping bgm preview of the selected song.
+//!
+//! The preview is decoded in the [`IO_POOL`], selecting another song bumps the generation and the
+//! outdated decoding stops at the next check.
+
+use crate::engine::global::{IO_POOL, STATIC_DATA};
+use crate::engine::OutputStreamHandle;
+use crate::game::song::SongInfo;
+use crate::game::{offset_type_to_secs, GameTimeType};
+use anyhow::anyhow;
+use rodio::buffer::SamplesBuffer;
+use rodio::{Decoder, Sink, Source};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The fade in and fade out length.
+const FADE_SECS: GameTimeType = 1.0;
+/// Where the preview starts if the song has no preview start.
+const DEFAULT_PREVIEW_RATIO: f64 = 0.4;
+/// Check the generation every so many samples when decoding.
+const CANCEL_CHECK_SAMPLES: usize = 4096;
+
+pub struct SongPreview {
+    handle: OutputStreamHandle,
+    /// The bgm file of the song previewing or decoding.
+    current: Option<PathBuf>,
+    generation: Arc<AtomicU64>,
+    sink: Arc<Mutex<Option<Sink>>>,
+}
+
+impl SongPreview {
+    pub fn new(handle: OutputStreamHandle) -> Self {
+        Self {
+            handle,
+            current: None,
+            generation: Default::default(),
+            sink: Default::default(),
+        }
+    }
+
+    /// Preview the song, nothing happens if it is previewing already.
+    pub fn play(&mut self, song: &SongInfo) {
+        if self.current.as_ref() == Some(&song.bgm_file) {
+            return;
+        }
+        self.stop();
+        self.current = Some(song.bgm_file.clone());
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let bgm_file = song.bgm_file.clone();
+        let start = song.metadata.preview_start.map(offset_type_to_secs);
+        let end = song.metadata.preview_end.map(offset_type_to_secs);
+        let handle = self.handle.clone();
+        let shared_generation = self.generation.clone();
+        let shared_sink = self.sink.clone();
+        IO_POOL.spawn_ok(async move {
+            let is_outdated = || shared_generation.load(Ordering::Acquire) != generation;
+            let buffer = match decode_preview(&bgm_file, start, end, is_outdated) {
+                Ok(Some(x)) => x,
+                Ok(None) => return,
+                Err(e) => {
+                    log::warn!("Failed to decode the preview {:?} for {:?}", bgm_file, e);
+                    return;
+                }
+            };
+            let vol = match STATIC_DATA.cfg_data.write() {
+                Ok(mut cfg) => cfg.get_f32_def("bgm_vol", 1.0),
+                Err(e) => {
+                    log::warn!("Cannot write lock for {:?}", e);
+                    1.0
+                }
+            };
+
+            let mut sink_slot = shared_sink.lock().unwrap();
+            // check again in the lock, the stop takes the sink in the lock.
+            if is_outdated() {
+                return;
+            }
+            let sink = Sink::connect_new(&handle);
+            sink.set_volume(vol);
+            sink.append(buffer.repeat_infinite());
+            *sink_slot = Some(sink);
+        });
+    }
+
+    /// Stop the preview and cancel the decoding.
+    pub fn stop(&mut self) {
+        self.current = None;
+        let mut sink_slot = self.sink.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        if let Some(sink) = sink_slot.take() {
+            sink.stop();
+        }
+    }
+}
+
+impl Drop for SongPreview {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Decode the preview part with the fade in and fade out, none if cancelled.
+fn decode_preview(
+    bgm_file: &Path,
+    start: Option<GameTimeType>,
+    end: Option<GameTimeType>,
+    is_outdated: impl Fn() -> bool,
+) -> anyhow::Result<Option<SamplesBuffer>> {
+    let decoder = Decoder::new(BufReader::new(std::fs::File::open(bgm_file)?))?;
+    let channels = decoder.channels();
+    let sample_rate = decoder.sample_rate();
+    let start = start.unwrap_or_else(|| {
+        decoder
+            .total_duration()
+            .map(|x| x.as_secs_f64() * DEFAULT_PREVIEW_RATIO)
+            .unwrap_or(0.0)
+    });
+
+    let frame_samples = channels as usize;
+    let to_samples = |secs: GameTimeType| (secs * sample_rate as f64) as usize * frame_samples;
+    let skip = to_samples(start.max(0.0));
+    let limit = end
+        .map(|end| to_samples((end - start).max(0.0)))
+        .unwrap_or(usize::MAX);
+
+    let mut samples = vec![];
+    for (idx, sample) in decoder.skip(skip).take(limit).enumerate() {
+        if idx % CANCEL_CHECK_SAMPLES == 0 && is_outdated() {
+            return Ok(None);
+        }
+        samples.push(sample);
+    }
+    if samples.is_empty() {
+        return Err(anyhow!("No samples after the preview start {}s", start));
+    }
+
+    apply_fade(&mut samples, frame_samples, to_samples(FADE_SECS));
+    Ok(Some(SamplesBuffer::new(channels, sample_rate, samples)))
+}
+
+/// Fade in and fade out in `fade_samples` at both ends.
+fn apply_fade(samples: &mut [f32], frame_samples: usize, fade_samples: usize) {
+    let fade_frames = (fade_samples / frame_samples).min(samples.len() / frame_samples / 2);
+    if fade_frames == 0 {
+        return;
+    }
+    let frames = samples.len() / frame_samples;
+    for frame in 0..fade_frames {
+        let scale = frame as f32 / fade_frames as f32;
+        for x in &mut samples[frame * frame_samples..(frame + 1) * frame_samples] {
+            *x *= scale;
+        }
+        let tail = frames - 1 - frame;
+        for x in &mut samples[tail * frame_samples..(tail + 1) * frame_samples] {
+            *x *= scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::preview::apply_fade;
+
+    #[test]
+    fn test_fade() {
+        let mut samples = vec![1.0; 20];
+        apply_fade(&mut samples, 2, 6);
+        assert_eq!(
+            samples,
+            [
+                0.0, 0.0, 1.0 / 3.0, 1.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0, 1.0, 1.0, 1.0, 1.0, 1.0,
+                1.0, 1.0, 1.0, 2.0 / 3.0, 2.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0, 0.0, 0.0
+            ]
+        );
+    }
+}
@@ -237,7 +237,7 @@ impl NoteRenderDesc {
 }
 
 pub struct NoteRenderer {
-    pub gray_tint_buffer: Buffer,
+    pub _gray_tint_buffer: Buffer,
     pub gray_tint_bg: BindGroup,
     pub atlas_group: BindGroup,
     pub note_desc: NoteRenderDesc,
@@ -347,7 +347,7 @@ impl NoteRenderer {
         ];
         let slide_coords = Default::default();
         Self {
-            gray_tint_buffer,
+            _gray_tint_buffer: gray_tint_buffer,
             gray_tint_bg,
             atlas_group,
             note_desc: NoteRenderDesc {
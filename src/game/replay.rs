@@ -0,0 +1,276 @@
+//! Record the inputs of a play and feed them back into [`Gaming`].
+//!
+//! The judgement only depends on the inputs and their times, so the playback gets the same
+//! [`crate::game::beatmap::summary::BeatmapPlayResult`] however often it ticks.
+
+use crate::game::beatmap::file::{de_from_ron, ser_to_ron, SongBeatmapFile};
+use crate::game::beatmap::play::{Gaming, NoteHitResult, NoteJudgement, PlayOptions};
+use crate::game::beatmap::{GamePos, SongBeatmapInfo};
+use crate::game::song::{sanitize_file_name, SongInfo, SongManager};
+use crate::game::OffsetType;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+pub const REPLAY_EXT: &'static str = "rrp";
+const REPLAY_VERSION: u8 = 0;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ReplayInputKind {
+    Press,
+    Move,
+    Release,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayInput {
+    pub kind: ReplayInputKind,
+    pub time: OffsetType,
+    pub x: f32,
+    pub pointer: u64,
+}
+
+impl ReplayInput {
+    pub fn new(kind: ReplayInputKind, pos: GamePos, pointer: u64) -> Self {
+        Self {
+            kind,
+            time: pos.time,
+            x: pos.x,
+            pointer,
+        }
+    }
+
+    /// Send the input to the game, return the judgement of the press or the release.
+    pub fn apply(&self, gaming: &mut Gaming) -> Option<NoteJudgement> {
+        let pos = GamePos::new(self.x, self.time);
+        match self.kind {
+            ReplayInputKind::Press => gaming.process_input(pos, self.pointer),
+            ReplayInputKind::Move => {
+                gaming.process_input_move(pos, self.pointer);
+                None
+            }
+            ReplayInputKind::Release => gaming.process_input_leave(pos, self.pointer),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u8,
+    /// The song dir name, the key of the song in the [`SongManager`].
+    pub song: String,
+    /// The beatmap file name in the song dir.
+    pub beatmap: String,
+    /// The sha256 of the played beatmap, see [`get_beatmap_hash`].
+    pub beatmap_hash: String,
+    pub options: PlayOptions,
+    pub inputs: Vec<ReplayInput>,
+    /// The score of the recorded play, to check the playback.
+    pub score: u32,
+}
+
+/// The sha256 in hex of the canonical beatmap file.
+pub fn get_beatmap_hash(beatmap: &SongBeatmapFile) -> anyhow::Result<String> {
+    let digest = Sha256::digest(beatmap.to_pretty_ron()?.as_bytes());
+    Ok(format!("{:x}", digest))
+}
+
+/// The dir to save the replays, in the user data dir.
+pub fn get_replay_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|x| x.join("rust_rhythm"))
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default()
+        .join("replays")
+}
+
+impl Replay {
+    /// Record the play of the beatmap `beatmap` in the song `song`.
+    pub fn new(song: String, beatmap: String, gaming: &Gaming) -> anyhow::Result<Self> {
+        Ok(Self {
+            version: REPLAY_VERSION,
+            song,
+            beatmap,
+            beatmap_hash: get_beatmap_hash(&gaming.raw_file)?,
+            options: gaming.ops,
+            inputs: gaming.get_inputs().to_vec(),
+            score: gaming.score_counter.get_score(),
+        })
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let replay: Self = de_from_ron(&std::fs::read(path)?)?;
+        if replay.version > REPLAY_VERSION {
+            return Err(anyhow!("Unsupported replay version {}", replay.version));
+        }
+        Ok(replay)
+    }
+
+    /// Save the replay into the dir, return the saved file.
+    pub fn save(&self, dir: &Path) -> anyhow::Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let secs = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        let name = sanitize_file_name(&format!("{} {} {}", self.song, self.beatmap, secs));
+        let path = dir.join(format!("{}.{}", name, REPLAY_EXT));
+        let mut data = vec![];
+        ser_to_ron(self, &mut data, None)?;
+        std::fs::write(&path, data)?;
+        Ok(path)
+    }
+
+    /// Find the recorded song and beatmap in the manager.
+    pub fn find_beatmap(&self, manager: &SongManager) -> anyhow::Result<(Arc<SongInfo>, SongBeatmapInfo)> {
+        let song = manager
+            .songs
+            .get(&self.song)
+            .map(|x| x.value().clone())
+            .ok_or(anyhow!("No song {} for the replay", self.song))?;
+        let beatmap = song
+            .maps
+            .iter()
+            .find(|x| x.file_path.file_name().is_some_and(|x| x.to_string_lossy() == self.beatmap))
+            .cloned()
+            .ok_or(anyhow!("No beatmap {} in the song {}", self.beatmap, self.song))?;
+        Ok((song, beatmap))
+    }
+
+    /// Load the game to play back, the beatmap should be the recorded one.
+    pub fn load_game(&self, beatmap: SongBeatmapFile) -> anyhow::Result<Gaming> {
+        let gaming = Gaming::load_game(beatmap, self.options);
+        if get_beatmap_hash(&gaming.raw_file)? != self.beatmap_hash {
+            return Err(anyhow!("The beatmap is changed since the replay recorded"));
+        }
+        Ok(gaming)
+    }
+}
+
+/// Feed the recorded inputs into the game in order.
+pub struct ReplayPlayer {
+    replay: Replay,
+    next: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        Self { replay, next: 0 }
+    }
+
+    pub fn get_replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Feed from the first input again, for the restarted game.
+    pub fn restart(&mut self) {
+        self.next = 0;
+    }
+
+    /// Feed the inputs until the `time`, the callback gets the results of press and release.
+    pub fn feed(&mut self, gaming: &mut Gaming, time: OffsetType, mut callback: impl FnMut(NoteHitResult)) {
+        while let Some(input) = self.replay.inputs.get(self.next).filter(|x| x.time <= time) {
+            if let Some(judgement) = input.apply(gaming) {
+                callback(judgement.result);
+            }
+            self.next += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::beatmap::file::SongBeatmapFile;
+    use crate::game::beatmap::play::{Gaming, JudgePart, NoteJudgement, NoteResult, PlayOptions};
+    use crate::game::beatmap::summary::BeatmapPlayResult;
+    use crate::game::beatmap::{GamePos, FOUR_KEY_X};
+    use crate::game::beatmap::test::{lane_long_note, lane_note};
+    use crate::game::note::NoteHitType;
+    use crate::game::replay::{Replay, ReplayPlayer};
+    use crate::game::{offset_type_to_secs, OffsetType};
+
+    fn tick(gaming: &mut Gaming, time: OffsetType) {
+        gaming.tick(
+            offset_type_to_secs(time),
+            None::<fn(NoteJudgement)>,
+        );
+    }
+
+    /// The counts of every part and result, and the max combo.
+    fn get_counts(gaming: &Gaming) -> Vec<u32> {
+        use NoteResult::*;
+        let counter = &gaming.score_counter;
+        let mut counts = vec![counter.get_max_combo()];
+        for part in [JudgePart::Note, JudgePart::Head, JudgePart::Tail] {
+            for result in [Miss, Bad, Good, Great, Perfect] {
+                counts.push(counter.get_part_count(part, result));
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut beatmap = SongBeatmapFile::new("Song".into());
+        for (lane, time, note_type) in [
+            (0, 1000, NoteHitType::Click),
+            (1, 1200, NoteHitType::Slide),
+            (2, 1500, NoteHitType::Click),
+        ] {
+            beatmap.normal_notes.push(lane_note(lane, time, note_type));
+        }
+        beatmap.long_notes.push(lane_long_note(3, 1600, 2500));
+        beatmap.update();
+
+        let mut gaming = Gaming::load_game(beatmap.clone(), PlayOptions::default());
+        let inputs = [
+            (1020, 0, 0, true),
+            (1060, 0, 0, false),
+            (1100, 1, 1, true),
+            (1300, 1, 1, false),
+            (1650, 3, 3, true),
+            (2300, 3, 3, false),
+        ];
+        // tick every 16ms when playing.
+        let mut next = 0;
+        for time in (0..3000).step_by(16) {
+            while let Some((t, lane, p, press)) = inputs.get(next).filter(|x| x.0 <= time) {
+                let pos = GamePos::new(FOUR_KEY_X[*lane], *t);
+                if *press {
+                    gaming.process_input(pos, *p);
+                } else {
+                    gaming.process_input_leave(pos, *p);
+                }
+                next += 1;
+            }
+            tick(&mut gaming, time);
+        }
+        assert!(gaming.is_end());
+        let expected = BeatmapPlayResult::from_game(&gaming);
+        let expected_counts = get_counts(&gaming);
+
+        let dir = std::env::temp_dir().join("rr_replay_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let replay = Replay::new("Song".into(), "Song.rr".into(), &gaming).unwrap();
+        let replay = Replay::load(&replay.save(&dir).unwrap()).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // play back with another tick rate.
+        let mut gaming = replay.load_game(beatmap.clone()).unwrap();
+        let mut player = ReplayPlayer::new(replay);
+        for time in (0..3000).step_by(7) {
+            player.feed(&mut gaming, time, |_| {});
+            tick(&mut gaming, time);
+        }
+        assert!(gaming.is_end());
+        let result = BeatmapPlayResult::from_game(&gaming);
+        assert_eq!(result, expected);
+        assert_eq!(get_counts(&gaming), expected_counts);
+        assert_eq!(result.score, player.get_replay().score);
+
+        beatmap.normal_notes[0].time += 1;
+        assert!(player.get_replay().load_game(beatmap).is_err());
+    }
+}
@@ -0,0 +1,341 @@
+//! Play the beatmap without the audio, the window or the gpu.
+//!
+//! The inputs are the same as the recorded [`ReplayInput`], so a script can be written by hand or
+//! taken from a replay.
+
+use crate::game::beatmap::file::SongBeatmapFile;
+use crate::game::beatmap::health::HealthCounter;
+use crate::game::beatmap::play::{
+    Gaming, JudgePart, NoteHitResult, NoteJudgement, PlayOptions, ScoreCounter,
+};
+use crate::game::replay::ReplayInput;
+use crate::game::{offset_type_to_secs, OffsetType};
+
+pub struct SimulateResult {
+    pub score_counter: ScoreCounter,
+    pub health: HealthCounter,
+    /// The judgements in the judged order.
+    pub judgements: Vec<NoteJudgement>,
+    /// Whether all the notes are judged.
+    pub is_end: bool,
+}
+
+impl SimulateResult {
+    /// The result of the note part, the note index is the same as [`NoteJudgement::note_idx`].
+    pub fn get_result(&self, note_idx: usize, part: JudgePart) -> Option<NoteHitResult> {
+        self.judgements
+            .iter()
+            .find(|x| x.note_idx == note_idx && x.part == part)
+            .map(|x| x.result)
+    }
+}
+
+/// Play the beatmap with the inputs, ticking the game at the `ticks` in ms.
+///
+/// The inputs are sent in the time order, the inputs at a tick are sent before ticking. The inputs
+/// after the last tick are sent at the end, with a tick at the last of them.
+pub fn simulate(
+    beatmap: SongBeatmapFile,
+    ops: PlayOptions,
+    inputs: &[ReplayInput],
+    ticks: impl IntoIterator<Item = OffsetType>,
+) -> SimulateResult {
+    let mut gaming = Gaming::load_game(beatmap, ops);
+    let mut inputs = inputs.to_vec();
+    inputs.sort_by_key(|x| x.time);
+    let mut inputs = inputs.into_iter().peekable();
+    let mut judgements = vec![];
+
+    for time in ticks {
+        while let Some(input) = inputs.next_if(|x| x.time <= time) {
+            judgements.extend(input.apply(&mut gaming));
+        }
+        gaming.tick(offset_type_to_secs(time), Some(|x| judgements.push(x)));
+    }
+    let mut last_time = None;
+    for input in inputs {
+        judgements.extend(input.apply(&mut gaming));
+        last_time = Some(input.time);
+    }
+    if let Some(time) = last_time {
+        gaming.tick(offset_type_to_secs(time), Some(|x| judgements.push(x)));
+    }
+
+    SimulateResult {
+        is_end: gaming.is_end(),
+        score_counter: gaming.score_counter,
+        health: gaming.health,
+        judgements,
+    }
+}
+
+/// The ticks from 0 to `end` in every `step` ms, and the `end` itself.
+pub fn ticks_every(end: OffsetType, step: usize) -> impl Iterator<Item = OffsetType> {
+    (0..end).step_by(step).chain(std::iter::once(end))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::beatmap::file::SongBeatmapFile;
+    use crate::game::beatmap::play::{JudgePart, NoteResult, PlayOptions};
+    use crate::game::beatmap::{GamePos, FOUR_KEY_X};
+    use crate::game::beatmap::test::{lane_long_note, lane_note};
+    use crate::game::note::{LongNote, NormalNote, NoteHitType};
+    use crate::game::replay::{ReplayInput, ReplayInputKind};
+    use crate::game::simulate::{simulate, ticks_every};
+    use crate::game::OffsetType;
+
+    struct Case {
+        name: &'static str,
+        notes: &'static [(usize, OffsetType)],
+        long_notes: &'static [(usize, OffsetType, OffsetType)],
+        /// The pointer is the lane, true for the press.
+        inputs: &'static [(OffsetType, usize, bool)],
+        /// The results of the notes, the long notes have the head and the tail.
+        results: &'static [(usize, JudgePart, NoteResult)],
+        score: u32,
+        drain_level: Option<f32>,
+        /// The time the health ran out, none if not failed.
+        failed_time: Option<OffsetType>,
+    }
+
+    fn load_beatmap(case: &Case) -> SongBeatmapFile {
+        let mut beatmap = SongBeatmapFile::new("Song".into());
+        beatmap.normal_notes = case
+            .notes
+            .iter()
+            .map(|(lane, time)| lane_note(*lane, *time, NoteHitType::Click))
+            .collect();
+        beatmap.long_notes = case
+            .long_notes
+            .iter()
+            .map(|(lane, start_time, end_time)| lane_long_note(*lane, *start_time, *end_time))
+            .collect();
+        beatmap.drain_level = case.drain_level;
+        beatmap.update();
+        beatmap
+    }
+
+    fn get_inputs(case: &Case) -> Vec<ReplayInput> {
+        case.inputs
+            .iter()
+            .map(|(time, lane, press)| {
+                let kind = if *press { ReplayInputKind::Press } else { ReplayInputKind::Release };
+                ReplayInput::new(kind, GamePos::new(FOUR_KEY_X[*lane], *time), *lane as u64)
+            })
+            .collect()
+    }
+
+    const CASES: &[Case] = &[
+        Case {
+            name: "perfect",
+            notes: &[(0, 1000), (1, 1500)],
+            long_notes: &[],
+            inputs: &[(1010, 0, true), (1050, 0, false), (1480, 1, true), (1500, 1, false)],
+            results: &[(0, JudgePart::Note, NoteResult::Perfect), (1, JudgePart::Note, NoteResult::Perfect)],
+            score: 1_000_000,
+            drain_level: None,
+            failed_time: None,
+        },
+        Case {
+            name: "late miss",
+            notes: &[(0, 1000), (1, 1500)],
+            long_notes: &[],
+            // too late to hit, the note is missed when it leaves the window.
+            inputs: &[(1160, 0, true), (1200, 0, false), (1545, 1, true), (1600, 1, false)],
+            results: &[(0, JudgePart::Note, NoteResult::Miss), (1, JudgePart::Note, NoteResult::Great)],
+            score: 250_000,
+            drain_level: None,
+            failed_time: None,
+        },
+        Case {
+            name: "early miss",
+            notes: &[(0, 1000), (0, 1400)],
+            long_notes: &[],
+            // the early press misses the first note only.
+            inputs: &[(820, 0, true), (850, 0, false), (1310, 0, true), (1330, 0, false)],
+            results: &[(0, JudgePart::Note, NoteResult::Miss), (1, JudgePart::Note, NoteResult::Good)],
+            score: 125_000,
+            drain_level: None,
+            failed_time: None,
+        },
+        Case {
+            name: "long note hold",
+            notes: &[],
+            long_notes: &[(0, 1000, 2000), (1, 2500, 3000), (2, 3500, 4000)],
+            inputs: &[
+                (1000, 0, true),
+                (2100, 0, false),
+                (2540, 1, true),
+                (2890, 1, false),
+                (3500, 2, true),
+                (3600, 2, false),
+            ],
+            results: &[
+                (0, JudgePart::Head, NoteResult::Perfect),
+                (0, JudgePart::Tail, NoteResult::Perfect),
+                (1, JudgePart::Head, NoteResult::Great),
+                (1, JudgePart::Tail, NoteResult::Great),
+                (2, JudgePart::Head, NoteResult::Perfect),
+                (2, JudgePart::Tail, NoteResult::Miss),
+            ],
+            score: 666_666,
+            drain_level: None,
+            failed_time: None,
+        },
+        Case {
+            name: "chord",
+            notes: &[(0, 1000), (1, 1000), (2, 1000), (3, 1500)],
+            long_notes: &[(3, 1000, 1400)],
+            inputs: &[
+                (995, 0, true),
+                (1000, 1, true),
+                (1040, 2, true),
+                (1005, 3, true),
+                (1050, 0, false),
+                (1050, 1, false),
+                (1050, 2, false),
+                (1400, 3, false),
+                (1520, 3, true),
+                (1550, 3, false),
+            ],
+            results: &[
+                (0, JudgePart::Note, NoteResult::Perfect),
+                (1, JudgePart::Note, NoteResult::Perfect),
+                (2, JudgePart::Note, NoteResult::Great),
+                (3, JudgePart::Note, NoteResult::Perfect),
+                (4, JudgePart::Head, NoteResult::Perfect),
+                (4, JudgePart::Tail, NoteResult::Perfect),
+            ],
+            score: 916_666,
+            drain_level: None,
+            failed_time: None,
+        },
+        Case {
+            name: "fail",
+            notes: &[
+                (0, 1000),
+                (1, 1100),
+                (2, 1200),
+                (3, 1300),
+                (0, 1400),
+                (1, 1500),
+                (2, 1600),
+                (3, 1700),
+                (0, 2500),
+            ],
+            long_notes: &[(1, 1000, 3000)],
+            // the head is missed with the notes, the hits after failed are not counted.
+            inputs: &[(1400, 0, true), (1420, 0, false), (2500, 0, true), (2550, 0, false)],
+            results: &[
+                (0, JudgePart::Note, NoteResult::Miss),
+                (9, JudgePart::Head, NoteResult::Miss),
+                (9, JudgePart::Tail, NoteResult::Miss),
+                (1, JudgePart::Note, NoteResult::Miss),
+                (2, JudgePart::Note, NoteResult::Miss),
+                (3, JudgePart::Note, NoteResult::Miss),
+                (4, JudgePart::Note, NoteResult::Perfect),
+                (5, JudgePart::Note, NoteResult::Miss),
+                (6, JudgePart::Note, NoteResult::Miss),
+            ],
+            score: 90_909,
+            drain_level: Some(10.0),
+            failed_time: Some(1800),
+        },
+    ];
+
+    #[test]
+    fn test_falling_pointers() {
+        let mut beatmap = SongBeatmapFile::new("Song".into());
+        // a wide and a narrow note in a chord, then a tap while holding the long note.
+        for (x, width, time) in [(-0.5, 0.6, 1000), (0.3, 0.1, 1000), (0.6, 0.2, 1500)] {
+            beatmap.normal_notes.push(NormalNote {
+                x,
+                width,
+                time,
+                note_type: NoteHitType::Click,
+                timing_group: 0,
+            });
+        }
+        beatmap.long_notes.push(LongNote {
+            x: -0.2,
+            width: 0.3,
+            start_time: 1400,
+            end_time: 2000,
+            timing_group: 0,
+        });
+        beatmap.update();
+
+        let input = |kind, x, time, pointer| ReplayInput::new(kind, GamePos::new(x, time), pointer);
+        use ReplayInputKind::*;
+        let inputs = [
+            input(Press, -0.7, 1000, 1),
+            input(Press, 0.32, 1010, 2),
+            input(Release, -0.7, 1100, 1),
+            input(Release, 0.32, 1100, 2),
+            input(Press, -0.25, 1400, 1),
+            input(Press, 0.55, 1500, 2),
+            input(Release, 0.55, 1550, 2),
+            input(Release, -0.25, 2000, 1),
+        ];
+        let result = simulate(beatmap, PlayOptions::default(), &inputs, ticks_every(3000, 16));
+        assert!(result.is_end);
+        assert_eq!(result.judgements.len(), 5);
+        assert!(result.judgements.iter().all(|x| x.result.grade == NoteResult::Perfect));
+        assert_eq!(result.score_counter.get_score(), 1_000_000);
+    }
+
+    #[test]
+    fn test_long_note_moved() {
+        let mut beatmap = SongBeatmapFile::new("Song".into());
+        for (lane, start_time, end_time) in [(0, 1000, 2000), (2, 3000, 4000)] {
+            beatmap.long_notes.push(lane_long_note(lane, start_time, end_time));
+        }
+        beatmap.update();
+
+        let input = |kind, lane: usize, time, pointer| {
+            ReplayInput::new(kind, GamePos::new(FOUR_KEY_X[lane], time), pointer)
+        };
+        use ReplayInputKind::*;
+        let inputs = [
+            // moved off the long note, the tail is released there.
+            input(Press, 0, 1000, 1),
+            input(Move, 3, 1500, 1),
+            input(Release, 3, 2000, 1),
+            // moved onto the long note, it keeps holding after the first pointer is released.
+            input(Press, 2, 3000, 1),
+            input(Press, 0, 3100, 2),
+            input(Move, 2, 3200, 2),
+            input(Release, 2, 3300, 1),
+            input(Release, 0, 4000, 2),
+        ];
+        for step in [1, 7, 16, 33] {
+            let result = simulate(beatmap.clone(), PlayOptions::default(), &inputs, ticks_every(5000, step));
+            assert!(result.is_end);
+            assert_eq!(result.judgements.len(), 4);
+            assert_eq!(result.get_result(0, JudgePart::Tail).map(|x| x.grade), Some(NoteResult::Miss));
+            assert_eq!(result.get_result(1, JudgePart::Head).map(|x| x.grade), Some(NoteResult::Perfect));
+            assert_eq!(result.get_result(1, JudgePart::Tail).map(|x| x.grade), Some(NoteResult::Perfect));
+        }
+    }
+
+    #[test]
+    fn test_regression() {
+        for case in CASES {
+            let inputs = get_inputs(case);
+            // the results should not depend on the tick rate.
+            for step in [1, 7, 16, 33] {
+                let result = simulate(load_beatmap(case), PlayOptions::default(), &inputs, ticks_every(5000, step));
+                // the notes after failed are never judged.
+                assert_eq!(result.is_end, case.failed_time.is_none(), "{} is end", case.name);
+                assert_eq!(result.health.get_failed_time(), case.failed_time, "{} with step {}", case.name, step);
+                assert_eq!(result.judgements.len(), case.results.len(), "{}", case.name);
+                for (note_idx, part, expected) in case.results {
+                    let actual = result.get_result(*note_idx, *part).map(|x| x.grade);
+                    assert_eq!(actual, Some(*expected), "{} note {} {:?}", case.name, note_idx, part);
+                }
+                assert_eq!(result.score_counter.get_score(), case.score, "{} with step {}", case.name, step);
+            }
+        }
+    }
+}
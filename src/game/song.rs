@@ -1,44 +1,147 @@
-use crate::game::beatmap::file::{de_from_ron, SongBeatmapFile};
+use crate::game::beatmap::file::{de_from_ron, ser_to_ron, SongBeatmapFile};
+use crate::game::beatmap::osu::{parse_osu, OsuBeatmap, OSU_EXT};
+use crate::game::beatmap::sm::{parse_sm, StepManiaSong, SM_EXTS};
 use crate::game::beatmap::{SongBeatmapInfo, BEATMAP_EXT};
+use crate::game::song_index::{SongIndex, INDEX_FILE};
+use crate::game::song_watcher::SongWatcher;
+use crate::game::package::{
+    extract_package, read_package_title, write_package, PackageConflict, PACKAGE_EXT,
+};
+use crate::game::OffsetType;
 use anyhow::anyhow;
+use crate::engine::global::STATIC_DATA;
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use rayon::iter::ParallelBridge;
 use rayon::iter::ParallelIterator;
-use serde::Deserialize;
+use rodio::Decoder;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
 use std::fs::DirEntry;
+use std::io::{BufReader, Seek};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::AtomicBool;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Waker;
+use std::time::SystemTime;
+
+/// The config key of the song library roots in priority order.
+pub const SONG_ROOTS_KEY: &'static str = "song_roots";
+/// The config key of the root to import the songs into, the first writable root if not set.
+pub const SONG_IMPORT_ROOT_KEY: &'static str = "song_import_root";
+
+/// The song metadata file in the song dir.
+pub const SONG_METADATA_FILE: &'static str = "song.ron";
+
+/// The song level info, saved in [`SONG_METADATA_FILE`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SongMetadata {
+    /// The display title, the song dir name is shown if empty.
+    pub title: String,
+    pub artist: String,
+    /// The preview start in ms.
+    pub preview_start: Option<OffsetType>,
+    /// The preview end in ms, preview to the audio end if none.
+    pub preview_end: Option<OffsetType>,
+    /// The background image path relative to the song dir.
+    pub background: Option<String>,
+    /// The ms the notes are delayed against the audio.
+    pub audio_offset: OffsetType,
+}
 
 #[derive(Debug)]
 pub struct SongInfo {
     pub bgm_file: PathBuf,
+    /// The song dir name, also the key in the [`SongManager`].
     pub title: String,
+    /// The library root that the song dir is in.
+    pub root: PathBuf,
+    pub metadata: SongMetadata,
     pub maps: Vec<SongBeatmapInfo>,
+    /// When the song was added, the creation time of the song dir.
+    pub added: SystemTime,
     /// Should we reload the maps
     pub dirty: AtomicBool,
 }
 
 ///
 pub struct SongManager {
-    /// The songs dir
-    pub root: PathBuf,
+    /// The library roots in priority order, the song in the former root hides the same named one
+    /// in the latter roots.
+    pub roots: Vec<PathBuf>,
+    /// The root to import the songs into.
+    pub import_root: PathBuf,
     pub songs: DashMap<String, Arc<SongInfo>>,
+    /// Increased when the songs are added, removed or reloaded.
+    pub generation: AtomicU64,
+    watcher: Mutex<Option<SongWatcher>>,
 }
 
 pub type SongManagerResourceType = Arc<SongManager>;
 
+impl SongMetadata {
+    /// Load the metadata in the song dir, none if the song has no metadata file.
+    pub fn load(song_dir: &Path) -> anyhow::Result<Option<Self>> {
+        let path = song_dir.join(SONG_METADATA_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(de_from_ron(&std::fs::read(path)?)?))
+    }
+
+    pub fn save(&self, song_dir: &Path) -> anyhow::Result<()> {
+        let mut data = vec![];
+        ser_to_ron(self, &mut data, Some(PrettyConfig::default()))?;
+        std::fs::write(song_dir.join(SONG_METADATA_FILE), data)?;
+        Ok(())
+    }
+}
+
 impl SongInfo {
-    fn supported_bgm_format() -> &'static [&'static str] {
-        &["mp3", "ogg"]
+    pub fn supported_bgm_format() -> &'static [&'static str] {
+        &["mp3", "ogg", "wav", "flac"]
+    }
+
+    pub fn get_song_dir(&self) -> anyhow::Result<&Path> {
+        self.bgm_file.parent().ok_or(anyhow!("No bgm file parent"))
+    }
+
+    /// The title in the metadata, or the song dir name.
+    pub fn get_show_title(&self) -> &str {
+        if self.metadata.title.is_empty() {
+            &self.title
+        } else {
+            &self.metadata.title
+        }
+    }
+
+    pub fn get_background_file(&self) -> Option<PathBuf> {
+        let background = self.metadata.background.as_ref()?;
+        Some(self.get_song_dir().ok()?.join(background))
     }
 
     pub fn reload(&self) -> anyhow::Result<Self> {
-        Self::load(self.bgm_file.parent().ok_or(anyhow!("No bgm file parent"))?)
+        Self::load(self.get_song_dir()?)
     }
 
     pub fn load(song_dir_path: &Path) -> anyhow::Result<Self> {
+        Self::load_with_index(song_dir_path, None)
+    }
+
+    /// Load the song, the beatmap summaries are read from the index if not changed.
+    pub fn load_with_index(song_dir_path: &Path, index: Option<&SongIndex>) -> anyhow::Result<Self> {
+        Self::load_inner(song_dir_path, index, false)
+    }
+
+    /// Load the song, fail if any beatmap fails rather than skipping it.
+    pub fn load_strict(song_dir_path: &Path) -> anyhow::Result<Self> {
+        Self::load_inner(song_dir_path, None, true)
+    }
+
+    fn load_inner(song_dir_path: &Path, index: Option<&SongIndex>, strict: bool) -> anyhow::Result<Self> {
         let title = song_dir_path.file_name().unwrap().to_string_lossy().to_string();
+        let root = song_dir_path.parent().ok_or(anyhow!("No root for {:?}", song_dir_path))?;
 
         let bgm_file = Self::supported_bgm_format().iter().filter_map(|ext| {
             let bgm_file = song_dir_path.join("bgm.".to_string() + ext);
@@ -55,6 +158,14 @@ impl SongInfo {
             return Err(anyhow!("No bgm found in {:?}", &song_dir_path))
         };
 
+        let metadata = match SongMetadata::load(song_dir_path) {
+            Ok(x) => x.unwrap_or_default(),
+            Err(e) => {
+                log::warn!("Failed to load song metadata in {:?} for {:?}", song_dir_path, e);
+                SongMetadata::default()
+            }
+        };
+
 
         let mut maps = std::fs::read_dir(&song_dir_path)?.par_bridge()
             .filter_map(|x: std::io::Result<DirEntry>| {
@@ -73,35 +184,31 @@ impl SongInfo {
                 }
             })
             .map(|entry| -> anyhow::Result<SongBeatmapInfo> {
-                let data = std::fs::read(entry.path())?;
-                let beatmap = de_from_ron(&data)?;
-                let mut info = SongBeatmapInfo {
-                    file_path: entry.path(),
-                    song_beatmap_file: beatmap,
-                };
-                info.song_beatmap_file.update();
-                Ok(info)
+                match index {
+                    Some(index) => index.get_or_load(&entry.path()),
+                    None => SongBeatmapInfo::load(entry.path()),
+                }
             })
             .filter_map(|result| {
                 match result {
-                    Ok(x) => Some(x),
-                    Err(e) => {
+                    Err(e) if !strict => {
                         log::warn!("Failed to parse beatmap info, caused by {:?}", e);
                         None
                     }
+                    result => Some(result),
                 }
             })
-            .collect::<Vec<_>>();
-
-        std::fs::read_dir(&song_dir_path)?;
+            .collect::<anyhow::Result<Vec<_>>>()?;
 
-        maps.sort_by(|x, y| x.song_beatmap_file.metadata.version
-            .cmp(&y.song_beatmap_file.metadata.version));
+        maps.sort_by(|x, y| x.metadata.version.cmp(&y.metadata.version));
 
         let song_info = SongInfo {
             bgm_file,
             title: title.clone(),
+            root: root.to_path_buf(),
+            metadata,
             maps,
+            added: get_added_time(song_dir_path),
             dirty: Default::default(),
         };
 
@@ -110,24 +217,101 @@ impl SongInfo {
 }
 
 impl SongManager {
-    fn get_root() -> PathBuf {
-        std::env::current_dir()
-            .expect("Failed to get current dir")
-            .join("songs")
+    /// The roots used if the config has none: the user data dir, and the `songs` in the current
+    /// dir used by the old versions.
+    fn get_default_roots() -> Vec<PathBuf> {
+        let mut roots = vec![];
+        if let Some(dir) = dirs::data_dir() {
+            roots.push(dir.join("rust_rhythm").join("songs"));
+        }
+        if let Ok(dir) = std::env::current_dir() {
+            let legacy = dir.join("songs");
+            if legacy.is_dir() || roots.is_empty() {
+                roots.push(legacy);
+            }
+        }
+        roots
     }
 
+    /// Resolve the root in the config, the relative one is relative to the executable dir.
+    fn resolve_root(root: &str) -> PathBuf {
+        let root = PathBuf::from(root);
+        if root.is_relative() {
+            if let Some(dir) = std::env::current_exe().ok().and_then(|x| x.parent().map(Path::to_path_buf)) {
+                return dir.join(root);
+            }
+        }
+        root
+    }
 
-    pub fn init_manager() -> anyhow::Result<Self> {
-        let root = Self::get_root();
-        let _ = std::fs::create_dir_all(&root);
-        let this = Self {
-            root,
+    /// Read the roots and the import root in the config, the defaults are written if not set.
+    fn get_config_roots() -> anyhow::Result<(Vec<PathBuf>, Option<PathBuf>)> {
+        let mut cfg = STATIC_DATA
+            .cfg_data
+            .write()
+            .map_err(|e| anyhow!("Cannot write lock for {:?}", e))?;
+        let default_roots = Self::get_default_roots()
+            .iter()
+            .map(|x| x.to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        let roots = cfg
+            .get_str_array_def(SONG_ROOTS_KEY, &default_roots)
+            .iter()
+            .map(|x| Self::resolve_root(x))
+            .collect::<Vec<_>>();
+        let import_root = cfg.get_str(SONG_IMPORT_ROOT_KEY).map(Self::resolve_root);
+        Ok((roots, import_root))
+    }
+
+    /// Create the manager with the roots in priority order, the import root is added as the last
+    /// root if not in the roots.
+    pub fn new(mut roots: Vec<PathBuf>, import_root: PathBuf) -> Self {
+        if !roots.contains(&import_root) {
+            roots.push(import_root.clone());
+        }
+        Self {
+            roots,
+            import_root,
             songs: Default::default(),
-        };
+            generation: Default::default(),
+            watcher: Default::default(),
+        }
+    }
+
+    pub fn init_manager() -> anyhow::Result<Self> {
+        let (roots, import_root) = Self::get_config_roots()?;
+        // import into the chosen root, or the first writable root.
+        let import_root = import_root
+            .iter()
+            .chain(roots.iter())
+            .find(|x| match std::fs::create_dir_all(x) {
+                Ok(_) => true,
+                Err(e) => {
+                    log::warn!("Cannot use {:?} to import songs for {:?}", x, e);
+                    false
+                }
+            })
+            .cloned()
+            .ok_or(anyhow!("No writable song root in {:?}", roots))?;
+        let this = Self::new(roots, import_root);
+        log::info!("Song roots {:?}, import into {:?}", this.roots, this.import_root);
+
+        for root in this.roots.iter().filter(|x| x.is_dir()) {
+            if let Err(e) = this.load_root(root) {
+                log::error!("Failed to load the songs in {:?} for {:?}", root, e);
+            }
+        }
+
+        Ok(this)
+    }
 
+    /// Load all the songs in the root.
+    pub(crate) fn load_root(&self, root: &Path) -> anyhow::Result<()> {
+        let index_path = root.join(INDEX_FILE);
+        let index = SongIndex::load(&index_path);
 
         // Reload songs
-        std::fs::read_dir(&this.root)?.par_bridge()
+        std::fs::read_dir(root)?.par_bridge()
             .filter_map(|x: std::io::Result<DirEntry>| {
                 match x {
                     Ok(entry) => {
@@ -146,9 +330,9 @@ impl SongManager {
             .map(|x: DirEntry| -> anyhow::Result<()> {
                 let song_dir_path = x.path();
 
-                let song_info = SongInfo::load(&song_dir_path)?;
+                let song_info = SongInfo::load_with_index(&song_dir_path, Some(&index))?;
 
-                this.songs.insert(song_info.title.clone(), song_info.into());
+                self.insert_song(song_info);
 
                 Ok(())
             }).for_each(|x| {
@@ -157,47 +341,472 @@ impl SongManager {
             }
         });
 
-        Ok(this)
+        if let Err(e) = index.save(&index_path) {
+            log::warn!("Failed to save the song index for {:?}", e);
+        }
+        Ok(())
+    }
+
+    /// Watch the songs dir and reload the changed songs, `waker` is woken after reloading.
+    pub fn start_watch(self: &Arc<Self>, waker: Waker) -> anyhow::Result<()> {
+        let watcher = SongWatcher::new(self, waker)?;
+        *self.watcher.lock().map_err(|e| anyhow!("Cannot lock watcher for {:?}", e))? = Some(watcher);
+        Ok(())
     }
 
     pub fn load_new_info(&self, info: SongInfo) {
-        self.songs.insert(info.title.clone(), info.into());
+        self.insert_song(info);
     }
 
     pub fn import_song(&self, song: &Path) -> anyhow::Result<Arc<SongInfo>> {
         let filename = song.file_name().ok_or(anyhow!("No filename"))?.to_string_lossy();
 
-        let (filename_no_ext, ext) = match filename.split_once(".") {
+        let filename_no_ext = song
+            .file_stem()
+            .ok_or(anyhow!("No filename"))?
+            .to_string_lossy();
+        let ext = song
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        if !SongInfo::supported_bgm_format().iter().any(|x| ext == *x) {
+            return Err(anyhow!("Unsupported format for {}", filename));
+        }
+
+        let song_dir = self.copy_bgm(song, &filename_no_ext, &ext)?;
+        let bgm_file = song_dir.join("bgm.".to_string() + &ext);
+
+        let metadata = match SongMetadata::load(&song_dir)? {
             Some(x) => x,
             None => {
-                return Err(anyhow!("Unsupported format for {}", filename));
+                let metadata = SongMetadata {
+                    title: filename_no_ext.to_string(),
+                    ..Default::default()
+                };
+                metadata.save(&song_dir)?;
+                metadata
             }
         };
 
-        if !SongInfo::supported_bgm_format().iter().any(|x| ext == *x) {
-            return Err(anyhow!("Unsupported format for {}", filename));
-        }
+        let info = SongInfo {
+            bgm_file,
+            title: filename_no_ext.to_string(),
+            root: self.import_root.clone(),
+            metadata,
+            maps: vec![],
+            added: get_added_time(&song_dir),
+            dirty: AtomicBool::new(true),
+        };
+
+        self.insert_imported(info)
+    }
 
-        let song_dir = self.root.join(filename_no_ext);
+    /// Copy the bgm into the song dir named `dir_name`, return the song dir.
+    ///
+    /// The bgm is decoded first, so nothing is created for the broken audio or the hidden song.
+    fn copy_bgm(&self, song: &Path, dir_name: &str, ext: &str) -> anyhow::Result<PathBuf> {
+        self.check_hidden(dir_name)?;
+        probe_audio(song)?;
+
+        let song_dir = self.import_root.join(dir_name);
         std::fs::create_dir_all(&song_dir)?;
 
         let bgm_file = song_dir.join("bgm.".to_string() + ext);
 
         std::fs::copy(song, &bgm_file)?;
+        Ok(song_dir)
+    }
 
+    /// Import the chart file or the package by the extension.
+    pub fn import_chart(&self, chart_file: &Path) -> anyhow::Result<Arc<SongInfo>> {
+        let ext = chart_file
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if ext == OSU_EXT {
+            self.import_osu(chart_file)
+        } else if SM_EXTS.contains(&ext.as_str()) {
+            self.import_stepmania(chart_file, None)
+        } else if ext == PACKAGE_EXT {
+            self.import_package(chart_file, PackageConflict::Rename)
+        } else {
+            Err(anyhow!("Unsupported chart format for {:?}", chart_file))
+        }
+    }
 
-        let info = SongInfo {
-            bgm_file,
-            title: filename_no_ext.to_string(),
-            maps: vec![],
-            dirty: AtomicBool::new(true),
+    /// Import the osu!mania chart with the audio next to it.
+    ///
+    /// The song dir is named by the artist and title, so difficulties of the same set share the
+    /// same song.
+    pub fn import_osu(&self, osu_file: &Path) -> anyhow::Result<Arc<SongInfo>> {
+        let data = std::fs::read_to_string(osu_file)?;
+        let OsuBeatmap {
+            audio_filename,
+            preview_time,
+            background,
+            beatmap,
+        } = parse_osu(&data)?;
+        let metadata = SongMetadata {
+            preview_start: preview_time,
+            background,
+            ..Default::default()
         };
 
+        self.import_beatmaps(osu_file, &audio_filename, metadata, &[beatmap])
+    }
+
+    /// Import the stepmania chart with the music next to it.
+    ///
+    /// Every `dance-single` difficulty is saved as one beatmap, or only the `difficulty` if given.
+    pub fn import_stepmania(
+        &self,
+        sm_file: &Path,
+        difficulty: Option<&str>,
+    ) -> anyhow::Result<Arc<SongInfo>> {
+        let data = std::fs::read_to_string(sm_file)?;
+        let StepManiaSong {
+            music,
+            sample_start,
+            sample_length,
+            background,
+            mut charts,
+        } = parse_sm(&data)?;
+
+        if let Some(difficulty) = difficulty {
+            charts.retain(|x| x.metadata.version.eq_ignore_ascii_case(difficulty));
+            if charts.is_empty() {
+                return Err(anyhow!("No difficulty {} found", difficulty));
+            }
+        }
+
+        let metadata = SongMetadata {
+            preview_start: sample_start,
+            preview_end: sample_start.zip(sample_length).map(|(start, len)| start + len),
+            background,
+            ..Default::default()
+        };
+
+        self.import_beatmaps(sm_file, &music, metadata, &charts)
+    }
+
+    /// Copy the audio next to the chart file and save the beatmaps into the song dir.
+    ///
+    /// The `metadata` is saved if the song has no metadata yet, its background is relative to the
+    /// chart file and copied into the song dir. The title and artist are taken from the beatmap.
+    fn import_beatmaps(
+        &self,
+        chart_file: &Path,
+        audio_filename: &str,
+        mut metadata: SongMetadata,
+        beatmaps: &[SongBeatmapFile],
+    ) -> anyhow::Result<Arc<SongInfo>> {
+        let beatmap = beatmaps.first().ok_or(anyhow!("No beatmap to import"))?;
+        let chart_dir = chart_file
+            .parent()
+            .ok_or(anyhow!("No parent for {:?}", chart_file))?;
+        let audio = chart_dir.join(audio_filename);
+        if !audio.is_file() {
+            return Err(anyhow!("Cannot find the audio {:?}", audio));
+        }
+        let ext = audio
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if !SongInfo::supported_bgm_format().iter().any(|x| ext == *x) {
+            return Err(anyhow!("Unsupported format for {}", audio_filename));
+        }
+
+        let dir_name = sanitize_file_name(&format!(
+            "{} - {}",
+            beatmap.metadata.artist, beatmap.metadata.title
+        ));
+        let song_dir = self.copy_bgm(&audio, &dir_name, &ext)?;
+        for beatmap in beatmaps {
+            beatmap.save_to(&song_dir.join(
+                sanitize_file_name(&beatmap.get_show_name()) + "." + BEATMAP_EXT,
+            ))?;
+        }
+
+        if SongMetadata::load(&song_dir)?.is_none() {
+            metadata.title = beatmap.metadata.title.clone();
+            metadata.artist = beatmap.metadata.artist.clone();
+            metadata.background = metadata
+                .background
+                .and_then(|x| copy_background(&chart_dir.join(x), &song_dir));
+            metadata.save(&song_dir)?;
+        }
+
+        self.insert_imported(SongInfo::load(&song_dir)?)
+    }
+
+    /// Export the song dir as the package.
+    pub fn export_package(&self, song: &SongInfo, path: &Path) -> anyhow::Result<()> {
+        let song_dir = song.get_song_dir()?;
+        let file = std::fs::File::create(path)?;
+        write_package(song_dir, &song.title, file)
+    }
+
+    /// Import the package into the songs dir.
+    pub fn import_package(
+        &self,
+        package: &Path,
+        conflict: PackageConflict,
+    ) -> anyhow::Result<Arc<SongInfo>> {
+        let default_title = package
+            .file_stem()
+            .ok_or(anyhow!("No filename"))?
+            .to_string_lossy();
+        let mut file = std::fs::File::open(package)?;
+        let title = read_package_title(&mut file, &default_title)?;
+        self.check_hidden(&title)?;
+        let created = conflict == PackageConflict::Rename || !self.import_root.join(&title).exists();
+        file.rewind()?;
+        let song_dir = extract_package(file, &self.import_root, &default_title, conflict)?;
+
+        let result = SongInfo::load(&song_dir).and_then(|info| self.insert_imported(info));
+        if result.is_err() && created {
+            // the renamed dir may be hidden too.
+            let _ = std::fs::remove_dir_all(&song_dir);
+        }
+        result
+    }
+
+    fn get_root_priority(&self, root: &Path) -> usize {
+        self.roots.iter().position(|x| x == root).unwrap_or(usize::MAX)
+    }
+
+    /// Fail if the song named `dir_name` in the import root is hidden by the same named song in a
+    /// former root.
+    fn check_hidden(&self, dir_name: &str) -> anyhow::Result<()> {
+        match self.songs.get(dir_name) {
+            Some(shown)
+                if self.get_root_priority(&shown.root) < self.get_root_priority(&self.import_root) =>
+            {
+                Err(anyhow!(
+                    "The imported song {} is hidden by the same named song in {:?}",
+                    dir_name,
+                    shown.root
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Insert the imported song, fail if it is hidden by the same named song in a former root.
+    fn insert_imported(&self, info: SongInfo) -> anyhow::Result<Arc<SongInfo>> {
+        self.check_hidden(&info.title)?;
+        Ok(self.insert_song(info))
+    }
+
+    /// Insert the loaded song, the replaced one is marked dirty to refresh the song list.
+    ///
+    /// The song is not inserted if hidden by the same named song in a former root.
+    pub(crate) fn insert_song(&self, info: SongInfo) -> Arc<SongInfo> {
         let info = Arc::new(info);
-        self.songs.insert(filename_no_ext.to_string(), info.clone());
+        match self.songs.entry(info.title.clone()) {
+            Entry::Occupied(mut entry) => {
+                let old = entry.get();
+                if old.root != info.root
+                    && self.get_root_priority(&old.root) < self.get_root_priority(&info.root)
+                {
+                    log::info!("The song {} in {:?} is hidden by {:?}", info.title, info.root, old.root);
+                    return info;
+                }
+                old.dirty.store(true, Ordering::Release);
+                entry.insert(info.clone());
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(info.clone());
+            }
+        }
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        info
+    }
+
+    /// Remove the song in the removed song dir, the removed one is marked dirty to refresh the
+    /// song list. The same named song in the other roots is loaded instead.
+    pub(crate) fn remove_song_dir(&self, song_dir: &Path) {
+        let Some(title) = song_dir.file_name().map(|x| x.to_string_lossy().to_string()) else {
+            return;
+        };
+        let removed = self
+            .songs
+            .remove_if(&title, |_, x| x.get_song_dir().map(|x| x == song_dir).unwrap_or(false));
+        let Some((_, old)) = removed else {
+            return;
+        };
+        old.dirty.store(true, Ordering::Release);
+        self.generation.fetch_add(1, Ordering::AcqRel);
+
+        let hidden = self.roots.iter().map(|x| x.join(&title)).find(|x| x.is_dir());
+        if let Some(hidden) = hidden {
+            match SongInfo::load(&hidden) {
+                Ok(info) => {
+                    self.insert_song(info);
+                }
+                Err(e) => log::warn!("Failed to load song for {:?}", e),
+            }
+        }
+    }
+}
+
+/// The creation time of the song dir, or the modified time if the platform cannot tell.
+fn get_added_time(song_dir: &Path) -> SystemTime {
+    std::fs::metadata(song_dir)
+        .and_then(|x| x.created().or_else(|_| x.modified()))
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Copy the background image into the song dir, return the file name in the song dir.
+pub fn copy_background(image: &Path, song_dir: &Path) -> Option<String> {
+    let file_name = sanitize_file_name(&image.file_name()?.to_string_lossy());
+    if let Err(e) = std::fs::copy(image, song_dir.join(&file_name)) {
+        log::warn!("Failed to copy the background {:?} for {:?}", image, e);
+        return None;
+    }
+    Some(file_name)
+}
+
+/// Check the audio can be decoded.
+pub fn probe_audio(path: &Path) -> anyhow::Result<()> {
+    let file = std::fs::File::open(path)?;
+    let mut decoder = Decoder::new(BufReader::new(file))
+        .map_err(|e| anyhow!("Cannot decode the audio {:?} for {}", path, e))?;
+    if decoder.next().is_none() {
+        return Err(anyhow!("No audio samples in {:?}", path));
+    }
+    Ok(())
+}
 
-        Ok(info)
+/// Replace the chars that cannot be used in file name.
+pub fn sanitize_file_name(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    let name = name.trim().trim_end_matches('.');
+    if name.is_empty() {
+        "_".to_string()
+    } else {
+        name.to_string()
     }
 }
 
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::game::song::{SongInfo, SongManager, SongMetadata};
+    use std::path::PathBuf;
+
+    /// The empty temp dir with the `songs` dir for the test `name`.
+    pub(crate) fn get_test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rr_test_{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("songs")).unwrap();
+        dir
+    }
+
+    /// 16 bit mono wav with some silent samples.
+    pub(crate) fn get_wav() -> Vec<u8> {
+        let samples = 100u32;
+        let data_len = samples * 2;
+        let mut wav = vec![];
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&44100u32.to_le_bytes());
+        wav.extend_from_slice(&(44100u32 * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        wav.resize(wav.len() + data_len as usize, 0);
+        wav
+    }
+
+    #[test]
+    fn test_import_song() {
+        let dir = get_test_dir("import");
+        let manager = SongManager::new(vec![], dir.join("songs"));
+
+        let song = dir.join("my.song.v2.WAV");
+        std::fs::write(&song, get_wav()).unwrap();
+        let info = manager.import_song(&song).unwrap();
+        assert_eq!(info.title, "my.song.v2");
+        assert_eq!(info.bgm_file, dir.join("songs").join("my.song.v2").join("bgm.wav"));
+        assert!(info.bgm_file.is_file());
+        assert_eq!(info.get_show_title(), "my.song.v2");
+
+        let song_dir = info.get_song_dir().unwrap();
+        let metadata = SongMetadata {
+            title: "My Song".into(),
+            artist: "Someone".into(),
+            preview_start: Some(1000),
+            preview_end: Some(2000),
+            background: Some("bg.png".into()),
+            audio_offset: -20,
+        };
+        metadata.save(song_dir).unwrap();
+        let info = SongInfo::load(song_dir).unwrap();
+        assert_eq!(info.metadata, metadata);
+        assert_eq!(info.get_show_title(), "My Song");
+        assert_eq!(info.get_background_file(), Some(song_dir.join("bg.png")));
 
+        let broken = dir.join("broken.flac");
+        std::fs::write(&broken, b"not a flac").unwrap();
+        assert!(manager.import_song(&broken).is_err());
+        assert!(!dir.join("songs").join("broken").exists());
+
+        assert!(manager.import_song(&dir.join("no_ext")).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_multiple_roots() {
+        let dir = get_test_dir("roots");
+        let roots = vec![dir.join("a"), dir.join("b")];
+        for root in &roots {
+            let song_dir = root.join("Song");
+            std::fs::create_dir_all(&song_dir).unwrap();
+            std::fs::write(song_dir.join("bgm.wav"), get_wav()).unwrap();
+        }
+        let manager = SongManager::new(roots.clone(), dir.join("songs"));
+        assert_eq!(manager.roots.last(), Some(&dir.join("songs")));
+
+        // the song in the former root is kept.
+        for root in roots.iter().rev() {
+            manager.insert_song(SongInfo::load(&root.join("Song")).unwrap());
+        }
+        assert_eq!(manager.songs.get("Song").unwrap().root, roots[0]);
+
+        // the hidden one is shown after removed.
+        manager.remove_song_dir(&roots[1].join("Song"));
+        assert_eq!(manager.songs.get("Song").unwrap().root, roots[0]);
+        std::fs::remove_dir_all(roots[0].join("Song")).unwrap();
+        manager.remove_song_dir(&roots[0].join("Song"));
+        assert_eq!(manager.songs.get("Song").unwrap().root, roots[1]);
+
+        let song = dir.join("new.wav");
+        std::fs::write(&song, get_wav()).unwrap();
+        let info = manager.import_song(&song).unwrap();
+        assert_eq!(info.root, dir.join("songs"));
+        assert!(dir.join("songs").join("new").join("bgm.wav").is_file());
+
+        // the imported song hidden by the former root is not a success.
+        let song = dir.join("Song.wav");
+        std::fs::write(&song, get_wav()).unwrap();
+        assert!(manager.import_song(&song).is_err());
+        assert_eq!(manager.songs.get("Song").unwrap().root, roots[1]);
+        assert!(!dir.join("songs").join("Song").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
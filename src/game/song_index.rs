@@ -0,0 +1,152 @@
+//! The on-disk cache of the beatmap summaries, so the startup only parses the changed beatmaps.
+//!
+//! Every entry is keyed by the beatmap path, and it is valid while the file modified time and
+//! size are the same.
+
+use crate::game::beatmap::file::{de_from_ron, ser_to_ron};
+use crate::game::beatmap::SongBeatmapInfo;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
+
+/// The index file in the songs dir.
+pub const INDEX_FILE: &'static str = "index.ron";
+/// Bump it when the cached info changes, the old index will be dropped.
+const INDEX_VERSION: u8 = 0;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct IndexEntry {
+    modified: SystemTime,
+    size: u64,
+    info: SongBeatmapInfo,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexFile {
+    version: u8,
+    entries: Vec<(PathBuf, IndexEntry)>,
+}
+
+#[derive(Default)]
+pub struct SongIndex {
+    /// The entries read from the index file.
+    cached: HashMap<PathBuf, IndexEntry>,
+    /// The entries of the beatmaps loaded this time.
+    loaded: DashMap<PathBuf, IndexEntry>,
+    /// Is any beatmap parsed rather than read from the cache.
+    changed: AtomicBool,
+}
+
+impl SongIndex {
+    /// Read the index file, the index is empty if the file is missing or outdated.
+    pub fn load(path: &Path) -> Self {
+        let cached = match Self::read_file(path) {
+            Ok(x) => x,
+            Err(e) => {
+                if path.exists() {
+                    log::warn!("Failed to read the song index {:?} for {:?}", path, e);
+                }
+                HashMap::new()
+            }
+        };
+        Self {
+            cached,
+            ..Default::default()
+        }
+    }
+
+    fn read_file(path: &Path) -> anyhow::Result<HashMap<PathBuf, IndexEntry>> {
+        let file = de_from_ron::<IndexFile>(&std::fs::read(path)?)?;
+        if file.version != INDEX_VERSION {
+            return Ok(HashMap::new());
+        }
+        Ok(file.entries.into_iter().collect())
+    }
+
+    /// Get the cached summary of the beatmap, or parse it if the file changed.
+    pub fn get_or_load(&self, file_path: &Path) -> anyhow::Result<SongBeatmapInfo> {
+        let file_metadata = std::fs::metadata(file_path)?;
+        let modified = file_metadata.modified()?;
+        let size = file_metadata.len();
+
+        let entry = match self.cached.get(file_path) {
+            Some(x) if x.modified == modified && x.size == size => x.clone(),
+            _ => {
+                self.changed.store(true, Ordering::Relaxed);
+                IndexEntry {
+                    modified,
+                    size,
+                    info: SongBeatmapInfo::load(file_path.to_path_buf())?,
+                }
+            }
+        };
+        let info = entry.info.clone();
+        self.loaded.insert(file_path.to_path_buf(), entry);
+        Ok(info)
+    }
+
+    /// Write the loaded entries, the removed beatmaps are dropped from the index.
+    ///
+    /// Nothing is written if the index is the same.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if !self.changed.load(Ordering::Relaxed) && self.loaded.len() == self.cached.len() {
+            return Ok(());
+        }
+        let mut entries = self
+            .loaded
+            .iter()
+            .map(|x| (x.key().clone(), x.value().clone()))
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let file = IndexFile {
+            version: INDEX_VERSION,
+            entries,
+        };
+        let mut data = vec![];
+        ser_to_ron(&file, &mut data, None)?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::beatmap::file::SongBeatmapFile;
+    use crate::game::song_index::SongIndex;
+
+    #[test]
+    fn test_index() {
+        let dir = std::env::temp_dir().join("rr_song_index_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let index_path = dir.join("index.ron");
+        let beatmap_path = dir.join("a.rr");
+        let mut beatmap = SongBeatmapFile::new("Song".into());
+        beatmap.metadata.version = "Hard".into();
+        beatmap.save_to(&beatmap_path).unwrap();
+
+        let index = SongIndex::load(&index_path);
+        assert_eq!(index.get_or_load(&beatmap_path).unwrap().metadata.version, "Hard");
+        index.save(&index_path).unwrap();
+
+        // the cached one is used even if the content is broken with the same size and time.
+        let modified = std::fs::metadata(&beatmap_path).unwrap().modified().unwrap();
+        let size = std::fs::metadata(&beatmap_path).unwrap().len() as usize;
+        std::fs::write(&beatmap_path, vec![b' '; size]).unwrap();
+        let file = std::fs::File::options().write(true).open(&beatmap_path).unwrap();
+        file.set_modified(modified).unwrap();
+        drop(file);
+        let index = SongIndex::load(&index_path);
+        assert_eq!(index.get_or_load(&beatmap_path).unwrap().metadata.version, "Hard");
+
+        // changed file is parsed again.
+        beatmap.metadata.version = "Normal".into();
+        beatmap.save_to(&beatmap_path).unwrap();
+        assert_eq!(index.get_or_load(&beatmap_path).unwrap().metadata.version, "Normal");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,235 @@
+//! Watch the song roots and reload the changed songs.
+//!
+//! The events are collected until the dir is quiet for [`DEBOUNCE`], then every changed song dir
+//! is reloaded. The song being copied or saved may fail to load, it is retried later and only
+//! warned after [`MAX_RETRIES`].
+//!
+//! The root not existing yet is waited by watching its parent, and loaded once created.
+
+use crate::game::song::{SongInfo, SongManager};
+use crate::game::song_index::INDEX_FILE;
+use notify::event::{AccessKind, AccessMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::Waker;
+use std::time::Duration;
+
+const DEBOUNCE: Duration = Duration::from_millis(300);
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRIES: u32 = 10;
+
+pub struct SongWatcher {
+    /// Dropping the watcher closes the channel and stops the reload thread.
+    _watcher: Arc<Mutex<RecommendedWatcher>>,
+}
+
+impl SongWatcher {
+    pub fn new(manager: &Arc<SongManager>, waker: Waker) -> anyhow::Result<Self> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })?;
+        let roots = manager.roots.clone();
+        let mut missing = vec![];
+        for root in &roots {
+            if root.is_dir() {
+                watcher.watch(root, RecursiveMode::Recursive)?;
+            } else {
+                missing.push(root.clone());
+            }
+        }
+        let mut waiting = Waiting {
+            watcher: Weak::new(),
+            missing,
+            parents: vec![],
+        };
+        waiting.watch_parents(&mut watcher);
+
+        let watcher = Arc::new(Mutex::new(watcher));
+        waiting.watcher = Arc::downgrade(&watcher);
+        let manager = Arc::downgrade(manager);
+        std::thread::Builder::new()
+            .name("Song Watcher".into())
+            .spawn(move || watch_loop(roots, waiting, manager, rx, waker))?;
+        Ok(Self { _watcher: watcher })
+    }
+}
+
+/// The roots not existing yet, waited by watching their nearest existing parents.
+struct Waiting {
+    /// Weak so that dropping the [`SongWatcher`] still closes the channel.
+    watcher: Weak<Mutex<RecommendedWatcher>>,
+    missing: Vec<PathBuf>,
+    parents: Vec<PathBuf>,
+}
+
+impl Waiting {
+    fn get_parents(&self) -> Vec<PathBuf> {
+        self.missing
+            .iter()
+            .filter_map(|x| x.ancestors().skip(1).find(|x| x.is_dir()))
+            .map(Path::to_path_buf)
+            .collect()
+    }
+
+    fn watch_parents(&mut self, watcher: &mut RecommendedWatcher) {
+        self.parents = self.get_parents();
+        for parent in &self.parents {
+            if let Err(e) = watcher.watch(parent, RecursiveMode::NonRecursive) {
+                log::warn!("Cannot wait for the song root in {:?} for {:?}", parent, e);
+            }
+        }
+    }
+
+    /// Watch and load the created roots, return true if any is loaded.
+    fn load_created(&mut self, manager: &SongManager) -> bool {
+        let created = self.missing.iter().any(|x| x.is_dir());
+        if !created && self.get_parents() == self.parents {
+            return false;
+        }
+        let Some(watcher) = self.watcher.upgrade() else {
+            return false;
+        };
+        let Ok(mut watcher) = watcher.lock() else {
+            return false;
+        };
+        let (created, missing): (Vec<_>, Vec<_>) = self.missing.drain(..).partition(|x| x.is_dir());
+        self.missing = missing;
+        for root in &created {
+            log::info!("The song root {:?} is created", root);
+            if let Err(e) = watcher.watch(root, RecursiveMode::Recursive) {
+                log::warn!("Cannot watch the song root {:?} for {:?}", root, e);
+            }
+            if let Err(e) = manager.load_root(root) {
+                log::error!("Failed to load the songs in {:?} for {:?}", root, e);
+            }
+        }
+        // a parent of the root may be created instead.
+        self.watch_parents(&mut watcher);
+        !created.is_empty()
+    }
+}
+
+/// Get the song dir that the path is in.
+fn get_song_dir(roots: &[PathBuf], path: &Path) -> Option<PathBuf> {
+    roots.iter().find_map(|root| {
+        match path.strip_prefix(root).ok()?.components().next()? {
+            Component::Normal(name) if name != INDEX_FILE => Some(root.join(name)),
+            _ => None,
+        }
+    })
+}
+
+fn is_modify_event(event: &Event) -> bool {
+    match event.kind {
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+        EventKind::Access(_) => false,
+        _ => true,
+    }
+}
+
+fn watch_loop(
+    roots: Vec<PathBuf>,
+    mut waiting: Waiting,
+    manager: Weak<SongManager>,
+    rx: Receiver<notify::Result<Event>>,
+    waker: Waker,
+) {
+    // the song dirs to reload with the retried times.
+    let mut pending: HashMap<PathBuf, u32> = HashMap::new();
+    let collect = |pending: &mut HashMap<PathBuf, u32>, event: notify::Result<Event>| {
+        match event {
+            Ok(event) if is_modify_event(&event) => {
+                for path in &event.paths {
+                    if let Some(song_dir) = get_song_dir(&roots, path) {
+                        pending.insert(song_dir, 0);
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Failed to watch the songs for {:?}", e),
+        }
+    };
+
+    loop {
+        let event = if pending.is_empty() {
+            rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            rx.recv_timeout(RETRY_DELAY)
+        };
+        match event {
+            Ok(event) => collect(&mut pending, event),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        loop {
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(event) => collect(&mut pending, event),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+
+        let Some(manager) = manager.upgrade() else {
+            return;
+        };
+        let mut changed = waiting.load_created(&manager);
+        pending.retain(|song_dir, retries| {
+            if !song_dir.is_dir() {
+                manager.remove_song_dir(song_dir);
+                changed = true;
+                return false;
+            }
+            match SongInfo::load_strict(song_dir) {
+                Ok(info) => {
+                    manager.insert_song(info);
+                    changed = true;
+                    false
+                }
+                Err(e) if *retries < MAX_RETRIES => {
+                    log::debug!("Retry loading the song {:?} for {:?}", song_dir, e);
+                    *retries += 1;
+                    true
+                }
+                Err(_) => {
+                    // load what we can, the broken beatmaps are warned.
+                    match SongInfo::load(song_dir) {
+                        Ok(info) => {
+                            manager.insert_song(info);
+                            changed = true;
+                        }
+                        Err(e) => log::warn!("Failed to reload song {:?} for {:?}", song_dir, e),
+                    }
+                    false
+                }
+            }
+        });
+        if changed {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::song_watcher::get_song_dir;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_song_dir() {
+        let roots = [PathBuf::from("/songs"), PathBuf::from("/more/songs")];
+        assert_eq!(
+            get_song_dir(&roots, Path::new("/songs/Song/a.rr")),
+            Some(PathBuf::from("/songs/Song"))
+        );
+        assert_eq!(
+            get_song_dir(&roots, Path::new("/more/songs/Song")),
+            Some(PathBuf::from("/more/songs/Song"))
+        );
+        assert_eq!(get_song_dir(&roots, Path::new("/songs/index.ron")), None);
+        assert_eq!(get_song_dir(&roots, Path::new("/other/Song")), None);
+    }
+}
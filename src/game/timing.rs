@@ -254,6 +254,19 @@ impl Timing {
             start_y: 0.0,
         }
     }
+
+    /// Create timing only set speed.
+    pub fn new_speed(speed: f32, offset: OffsetType, time_signature: NonZeroU8) -> Self {
+        Self {
+            set_bpm: None,
+            bpm: Bpm::default(),
+            offset,
+            time_signature,
+            set_speed: Some(speed),
+            speed,
+            start_y: 0.0,
+        }
+    }
 }
 
 impl TimingLine {
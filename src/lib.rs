@@ -5,7 +5,7 @@ use crate::state::{InitState, MenuState};
 use winit::event_loop::EventLoop;
 
 mod engine;
-mod game;
+pub mod game;
 mod state;
 mod ui;
 
@@ -3,8 +3,9 @@ use crate::engine::{
     get_edit_cache, sample_change_speed, GameState, LoopState, OutputStreamHandle, StateData, Trans,
 };
 use crate::game::beatmap::file::SongBeatmapFile;
+use crate::game::beatmap::lint::{has_error, lint_beatmap, Diagnostic};
 use crate::game::beatmap::{SongBeatmapInfo, BEATMAP_EXT};
-use crate::game::song::{SongInfo, SongManagerResourceType};
+use crate::game::song::{SongInfo, SongManagerResourceType, SongMetadata};
 use crate::game::timing::TimingGroupBeatIterator;
 use crate::game::{offset_type_to_secs, secs_to_offset_type, OffsetType};
 use crate::state::editor::note_editor::{BeatmapEditorData, PointerType};
@@ -72,11 +73,20 @@ pub struct BeatMapEditor {
 
     sample_info: SongSampleInfo,
 
-    current_editor: SubEditor,
+    pub(in crate::state::editor) current_editor: SubEditor,
     pub dirty: bool,
     /// allow update by input this render, for we may skip update due to some cases.
     pub allow_update: bool,
     play_speed: f32,
+    /// Save the beatmap even if the lint reports errors.
+    pub save_with_errors: bool,
+    /// The last save is refused for the lint errors, shown in the top panel.
+    save_blocked: bool,
+    /// The editing song metadata, saved with the beatmap.
+    pub song_metadata: SongMetadata,
+    pub song_metadata_dirty: bool,
+    /// The diagnostics shown in the settings, linted again if none.
+    pub(in crate::state::editor) diagnostics: Option<Vec<Diagnostic>>,
 }
 
 pub(in crate::state::editor) struct InputCache {
@@ -151,10 +161,12 @@ impl BeatMapEditor {
 
         let dirty = info.is_none();
         let current_editor = SubEditor::Timing;
-        let beatmap = info
-            .map(|x| x.song_beatmap_file)
-            .unwrap_or(SongBeatmapFile::new(song_info.title.clone()));
+        let beatmap = match info {
+            Some(info) => info.load_beatmap()?,
+            None => SongBeatmapFile::new(song_info.get_show_title().to_string()),
+        };
         let input_cache = InputCache::new(&beatmap);
+        let song_metadata = song_info.metadata.clone();
         Ok(Self {
             beatmap,
             song_info,
@@ -167,19 +179,61 @@ impl BeatMapEditor {
             dirty,
             allow_update: false,
             play_speed: 1.0,
+            save_with_errors: false,
+            save_blocked: false,
+            song_metadata,
+            song_metadata_dirty: false,
+            diagnostics: None,
         })
     }
 
-    pub fn save(&mut self, s: &mut StateData) {
-        if self.save_path.is_none()
-            && (self.beatmap.metadata.title.is_empty() || self.beatmap.metadata.version.is_empty())
-        {
+    /// Write the editing notes back to the beatmap.
+    pub fn sync_notes(&mut self) {
+        self.beatmap.normal_notes.clear();
+        self.input_cache
+            .edit_data
+            .normal_notes
+            .iter()
+            .for_each(|x| self.beatmap.normal_notes.extend_from_slice(&x.1));
+
+        self.beatmap.long_notes.clear();
+        self.input_cache
+            .edit_data
+            .long_notes
+            .iter()
+            .for_each(|x| self.beatmap.long_notes.extend_from_slice(&x.1));
+    }
+
+    /// Check the synced beatmap.
+    pub fn lint(&self) -> Vec<Diagnostic> {
+        lint_beatmap(&self.beatmap, Some(self.total_duration.as_millis() as OffsetType))
+    }
+
+    fn save_song_metadata(&mut self, s: &mut StateData) {
+        if !self.song_metadata_dirty {
             return;
         }
-        if !self.dirty {
-            return;
+        let result = self
+            .song_info
+            .get_song_dir()
+            .and_then(|dir| self.song_metadata.save(dir))
+            .and_then(|_| self.song_info.reload());
+        match result {
+            Ok(new_info) => {
+                let song_manager = s.wd.world.fetch::<SongManagerResourceType>().clone();
+                song_manager.load_new_info(new_info);
+                self.song_info.dirty.store(true, Ordering::Release);
+                self.song_metadata_dirty = false;
+            }
+            Err(e) => {
+                log::error!("Failed to save song metadata for {:?}", e);
+            }
         }
-        let path = self.save_path.get_or_insert_with(|| {
+    }
+
+    /// The saved path, or the new file named by the title and version in the song dir.
+    fn get_save_path(&self) -> PathBuf {
+        self.save_path.clone().unwrap_or_else(|| {
             self.song_info.bgm_file.parent().unwrap().join(
                 format!(
                     "{}[{}]",
@@ -187,25 +241,29 @@ impl BeatMapEditor {
                 ) + "."
                     + BEATMAP_EXT,
             )
-        });
-        let path = path.clone();
+        })
+    }
 
+    pub fn save(&mut self, s: &mut StateData) {
+        self.save_song_metadata(s);
+        if self.save_path.is_none()
+            && (self.beatmap.metadata.title.is_empty() || self.beatmap.metadata.version.is_empty())
         {
-            // deal notes
-            self.beatmap.normal_notes.clear();
-            self.input_cache
-                .edit_data
-                .normal_notes
-                .iter()
-                .for_each(|x| self.beatmap.normal_notes.extend_from_slice(&x.1));
-
-            self.beatmap.long_notes.clear();
-            self.input_cache
-                .edit_data
-                .long_notes
-                .iter()
-                .for_each(|x| self.beatmap.long_notes.extend_from_slice(&x.1));
+            return;
+        }
+        if !self.dirty {
+            return;
+        }
+
+        self.sync_notes();
+        if !self.save_with_errors && has_error(&self.lint()) {
+            log::warn!("Refuse to save the beatmap with errors, see the settings");
+            self.save_blocked = true;
+            return;
         }
+        self.save_blocked = false;
+        let path = self.get_save_path();
+        self.save_path = Some(path.clone());
         let beatmap = self.beatmap.clone();
         let info = self.song_info.clone();
         let song_manager =
@@ -325,6 +383,8 @@ impl GameState for BeatMapEditor {
         let mut tran = Trans::None;
         self.render_top_panel(s, ctx);
         if self.current_editor != SubEditor::Settings {
+            // the notes or timings may be edited out of the settings.
+            self.diagnostics = None;
             self.render_top_audio_wave(s, ctx);
         }
 
@@ -358,9 +418,32 @@ impl GameState for BeatMapEditor {
     fn stop(&mut self, s: &mut StateData) {
         // Do save work
         self.save(s);
+        // refused to save, keep the work in the backup out of the song dir.
+        if self.dirty && (self.save_blocked || self.save_path.is_some()) {
+            if let Some(name) = self.get_save_path().file_name() {
+                let dir = get_backup_dir().join(&self.song_info.title);
+                let backup = dir.join(name).with_extension(BEATMAP_EXT.to_string() + ".bak");
+                let result = std::fs::create_dir_all(&dir)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| self.beatmap.save_to(&backup));
+                match result {
+                    Ok(_) => log::info!("Saved the beatmap backup {:?}", backup),
+                    Err(e) => log::error!("Failed to save beatmap backup for {:?}", e),
+                }
+            }
+        }
     }
 }
 
+/// The dir to keep the beatmaps refused to save, in the user data dir.
+fn get_backup_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|x| x.join("rust_rhythm"))
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default()
+        .join("backups")
+}
+
 impl BeatMapEditor {
     /// Get the position in game progress
     fn get_progress(&self) -> Duration {
@@ -370,7 +453,7 @@ impl BeatMapEditor {
     }
 
     /// The pos in game pos
-    fn seek_to(&self, pos: Duration) {
+    pub(in crate::state::editor) fn seek_to(&self, pos: Duration) {
         if self.play_speed != 1.0 {
             self.sink
                 .try_seek(pos.mul((1.0 / self.play_speed) as u32))
@@ -444,6 +527,17 @@ impl BeatMapEditor {
                     if ui.add(button).clicked() {
                         self.current_editor = SubEditor::Settings;
                     }
+
+                    if self.save_blocked {
+                        let text = egui::RichText::new(
+                            "Not saved for the errors, see the diagnostics in Settings or check \
+                            Save with errors | 有错误未保存，请在设置中查看检查结果或勾选忽略错误保存",
+                        )
+                        .color(Color32::RED);
+                        if ui.link(text).clicked() {
+                            self.current_editor = SubEditor::Settings;
+                        }
+                    }
                 });
             });
     }
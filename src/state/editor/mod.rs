@@ -7,6 +7,7 @@ mod util;
 use crate::engine::{
     GameState, LoopState, StateData, StateEvent, Trans, WaitFutureState, WaitResult,
 };
+use crate::game::preview::SongPreview;
 use crate::game::song::{SongManager, SongManagerResourceType};
 use crate::state::editor::editor::BeatMapEditor;
 use crate::ui::song_list::SongListUi;
@@ -18,26 +19,31 @@ use winit::keyboard::{KeyCode, PhysicalKey};
 
 pub struct EditorMenu {
     ui: SongListUi,
+    preview: Option<SongPreview>,
+    /// The song manager generation of the shown songs.
+    song_generation: u64,
 }
 
 impl EditorMenu {
     pub fn new() -> Self {
         Self {
             ui: Default::default(),
+            preview: None,
+            song_generation: 0,
         }
     }
 
     fn update_ui(&mut self, s: &mut StateData) {
-        let songs =
-            s.wd.world
-                .get_mut::<SongManagerResourceType>()
-                .unwrap()
-                .songs
-                .iter()
-                .map(|x| x.value().clone())
-                .collect::<Vec<_>>();
+        let song_manager = s.wd.world.get_mut::<SongManagerResourceType>().unwrap();
+        let generation = song_manager.generation.load(Ordering::Acquire);
+        let songs = song_manager
+            .songs
+            .iter()
+            .map(|x| x.value().clone())
+            .collect::<Vec<_>>();
         if !songs.par_iter().any(|x| x.dirty.load(Ordering::Relaxed)) {
             self.ui.update_songs(songs);
+            self.song_generation = generation;
         }
     }
 }
@@ -45,15 +51,22 @@ impl EditorMenu {
 impl GameState for EditorMenu {
     fn start(&mut self, s: &mut StateData) -> LoopState {
         self.update_ui(s);
+        self.preview = s
+            .app
+            .audio
+            .as_ref()
+            .map(|x| SongPreview::new(x.stream_handle.clone()));
         LoopState::WAIT
     }
 
     fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
-        if self
-            .ui
-            .songs()
-            .par_iter()
-            .any(|x| x.dirty.load(Ordering::Relaxed))
+        let generation = s.wd.world.fetch::<SongManagerResourceType>().generation.load(Ordering::Acquire);
+        if generation != self.song_generation
+            || self
+                .ui
+                .songs()
+                .par_iter()
+                .any(|x| x.dirty.load(Ordering::Relaxed))
         {
             self.update_ui(s);
         }
@@ -121,6 +134,23 @@ impl GameState for EditorMenu {
                                 }));
                             }
                         }
+
+                        if ui.button("Chart | 谱面").clicked() {
+                            if let Some(chart) = util::select_chart_file(&s.app.window) {
+                                let song_manager =
+                                    s.wd.world
+                                        .get_mut::<Arc<SongManager>>()
+                                        .expect("How can we lost song manager")
+                                        .clone();
+
+                                tran = Trans::Push(WaitFutureState::wait_task(async move {
+                                    if let Err(e) = song_manager.import_chart(&chart) {
+                                        log::warn!("Failed to import chart for {:?}", e);
+                                    }
+                                    WaitResult::Function(Box::new(|_| Trans::None))
+                                }));
+                            }
+                        }
                     });
                 });
 
@@ -153,6 +183,14 @@ impl GameState for EditorMenu {
                 });
             });
 
+        if let Some(preview) = &mut self.preview {
+            // stop the preview when entering the other state.
+            match (&tran, self.ui.selected_song()) {
+                (Trans::None, Some(song)) => preview.play(song),
+                _ => preview.stop(),
+            }
+        }
+
         tran
     }
 
@@ -160,6 +198,7 @@ impl GameState for EditorMenu {
         match event {
             StateEvent::Resume => {
                 s.app.window.set_title("Rust Rhythm");
+                self.update_ui(s);
             }
             _ => {}
         }
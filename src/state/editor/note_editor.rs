@@ -1,7 +1,7 @@
 use crate::engine::renderer::texture_renderer::TextureRenderer;
 use crate::engine::{EguiExt, StateData};
 use crate::game::beatmap::file::SongBeatmapFile;
-use crate::game::beatmap::{GamePos, MapRule, FOUR_KEY_X};
+use crate::game::beatmap::{get_lane_note_width, get_lanes_x, GamePos};
 use crate::game::note::consts::NOTE_HEIGHT_PIXEL;
 use crate::game::note::{LongNote, NormalNote, Note, NoteHitType};
 use crate::game::render::NoteRenderer;
@@ -346,11 +346,8 @@ impl BeatMapEditor {
 
         let end_time = current_time + view_secs;
 
-        match self.beatmap.rule {
-            MapRule::Falling => {}
-            MapRule::FourKey => {
-                x = get_nearest_result(x, &FOUR_KEY_X, None);
-            }
+        if let Some(keys) = self.beatmap.rule.get_keys() {
+            x = get_nearest_result(x, &get_lanes_x(keys), None);
         }
 
         let select_time = (((y * view_secs) + current_time) * 1000.0).round() as OffsetType;
@@ -887,9 +884,9 @@ impl BeatMapEditor {
     #[inline]
     #[must_use]
     fn get_place_note_width(&self) -> f32 {
-        match self.beatmap.rule {
-            MapRule::Falling => self.input_cache.note_width,
-            MapRule::FourKey => 0.25,
+        match self.beatmap.rule.get_keys() {
+            Some(keys) => get_lane_note_width(keys),
+            None => self.input_cache.note_width,
         }
     }
 }
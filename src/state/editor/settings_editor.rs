@@ -1,10 +1,36 @@
 use crate::engine::StateData;
+use crate::game::beatmap::health::DEFAULT_DRAIN_LEVEL;
+use crate::game::beatmap::osu::{export_osu, OSU_EXT};
+use crate::game::beatmap::play::DEFAULT_JUDGE_LEVEL;
 use crate::game::beatmap::MapRule;
-use crate::state::editor::editor::BeatMapEditor;
-use egui::{Color32, Frame, Widget};
+use crate::game::package::PACKAGE_EXT;
+use crate::game::song::{copy_background, sanitize_file_name, SongManagerResourceType};
+use crate::game::beatmap::lint::Severity;
+use crate::game::OffsetType;
+use crate::state::editor::editor::{BeatMapEditor, SubEditor};
+use crate::state::editor::util;
+use anyhow::anyhow;
+use egui::{Color32, DragValue, Frame, Ui, Widget};
+use std::path::Path;
+use std::time::Duration;
 
 impl BeatMapEditor {
     pub fn render_settings_editor(&mut self, s: &mut StateData, ctx: &egui::Context) {
+        let can_export = self.beatmap.rule.get_keys().is_some();
+        let mut export = false;
+        let mut export_package = false;
+        let mut select_background = false;
+        let mut seek_to = None;
+        let current_time = self.input_cache.current_duration.as_millis() as OffsetType;
+        let diagnostics = match self.diagnostics.take() {
+            Some(x) => x,
+            None => {
+                self.sync_notes();
+                self.lint()
+            }
+        };
+        // lint again only if the settings below are changed.
+        let was_dirty = std::mem::replace(&mut self.dirty, false);
         egui::CentralPanel::default()
             .frame(Frame::none())
             .show(ctx, |ui| {
@@ -43,9 +69,179 @@ impl BeatMapEditor {
                         self.dirty |= edit(&mut self.beatmap.metadata.tags).ui(ui).changed();
                     });
 
-                    self.dirty |= ui.radio_value(&mut self.beatmap.rule, MapRule::Falling, "Falling").changed();
-                    self.dirty |= ui.radio_value(&mut self.beatmap.rule, MapRule::FourKey, "4K").changed();
+                    ui.horizontal_wrapped(|ui| {
+                        for rule in MapRule::all() {
+                            self.dirty |= ui.radio_value(&mut self.beatmap.rule, rule, rule.get_name()).changed();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(none_select_label("Judge level: "));
+                        let mut enabled = self.beatmap.judge_level.is_some();
+                        if ui.checkbox(&mut enabled, "").changed() {
+                            self.beatmap.judge_level = enabled.then_some(DEFAULT_JUDGE_LEVEL);
+                            self.dirty = true;
+                        }
+                        if let Some(level) = &mut self.beatmap.judge_level {
+                            self.dirty |= DragValue::new(level).range(0.0..=10.0).speed(0.1).ui(ui).changed();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(none_select_label("Drain level: "));
+                        let mut enabled = self.beatmap.drain_level.is_some();
+                        if ui.checkbox(&mut enabled, "").changed() {
+                            self.beatmap.drain_level = enabled.then_some(DEFAULT_DRAIN_LEVEL);
+                            self.dirty = true;
+                        }
+                        if let Some(level) = &mut self.beatmap.drain_level {
+                            self.dirty |= DragValue::new(level).range(0.0..=10.0).speed(0.1).ui(ui).changed();
+                        }
+                    });
+
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.add(none_select_label("Song | 歌曲: "));
+                    let metadata = &mut self.song_metadata;
+                    let mut song_changed = false;
+                    ui.horizontal(|ui| {
+                        ui.add(none_select_label("Title: "));
+                        song_changed |= edit(&mut metadata.title).ui(ui).changed();
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(none_select_label("Artist: "));
+                        song_changed |= edit(&mut metadata.artist).ui(ui).changed();
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(none_select_label("Preview start: "));
+                        song_changed |= edit_optional_time(ui, &mut metadata.preview_start, current_time);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(none_select_label("Preview end: "));
+                        song_changed |= edit_optional_time(ui, &mut metadata.preview_end, current_time);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(none_select_label("Background: "));
+                        ui.label(metadata.background.clone().unwrap_or("-".to_string()));
+                        select_background = ui.button("Select | 选择").clicked();
+                        if metadata.background.is_some() && ui.button("Clear | 清除").clicked() {
+                            metadata.background = None;
+                            song_changed = true;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(none_select_label("Audio offset: "));
+                        song_changed |= DragValue::new(&mut metadata.audio_offset).suffix("ms").ui(ui).changed();
+                    });
+                    self.song_metadata_dirty |= song_changed;
+
+                    ui.add_space(10.0);
+                    let export_button = egui::Button::new("Export osu! | 导出 osu!");
+                    export = ui.add_enabled(can_export, export_button).clicked();
+                    export_package = ui.button("Export package | 导出曲包").clicked();
+
+                    ui.add_space(10.0);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.add(none_select_label("Diagnostics | 检查: "));
+                        ui.checkbox(&mut self.save_with_errors, "Save with errors | 忽略错误保存");
+                    });
+                    if diagnostics.is_empty() {
+                        ui.add(none_select_label("No problem found | 没有发现问题"));
+                    }
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        for diagnostic in &diagnostics {
+                            let color = match diagnostic.severity {
+                                Severity::Warning => Color32::YELLOW,
+                                Severity::Error => Color32::RED,
+                            };
+                            let text = egui::RichText::new(diagnostic.to_string()).color(color);
+                            if ui.link(text).clicked() {
+                                seek_to = Some(diagnostic.time);
+                            }
+                        }
+                    });
                 })
             });
+        if !self.dirty {
+            self.diagnostics = Some(diagnostics);
+        }
+        self.dirty |= was_dirty;
+
+        if let Some(time) = seek_to {
+            self.seek_to(Duration::from_millis(time.max(0) as u64));
+            self.current_editor = SubEditor::Note;
+        }
+
+        if select_background {
+            if let Some(path) = util::select_image_file(&s.app.window) {
+                let background = self
+                    .song_info
+                    .get_song_dir()
+                    .ok()
+                    .and_then(|dir| copy_background(&path, dir));
+                if background.is_some() {
+                    self.song_metadata.background = background;
+                    self.song_metadata_dirty = true;
+                }
+            }
+        }
+
+        if export {
+            let file_name = sanitize_file_name(&self.beatmap.get_show_name()) + "." + OSU_EXT;
+            if let Some(path) = util::select_export_osu_file(&s.app.window, &file_name) {
+                if let Err(e) = self.export_osu_to(&path) {
+                    log::error!("Failed to export osu! chart for {:?}", e);
+                }
+            }
+        }
+
+        if export_package {
+            let file_name = sanitize_file_name(self.song_info.get_show_title()) + "." + PACKAGE_EXT;
+            if let Some(path) = util::select_export_package_file(&s.app.window, &file_name) {
+                self.save(s);
+                let song_manager = s.wd.world.fetch::<SongManagerResourceType>().clone();
+                if let Err(e) = song_manager.export_package(&self.song_info, &path) {
+                    log::error!("Failed to export package for {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// Export the beatmap as osu!mania chart, the bgm is copied next to it.
+    fn export_osu_to(&mut self, path: &Path) -> anyhow::Result<()> {
+        self.sync_notes();
+        let bgm_file = &self.song_info.bgm_file;
+        let audio_filename = bgm_file
+            .file_name()
+            .ok_or(anyhow!("No bgm file name"))?
+            .to_string_lossy()
+            .to_string();
+        let data = export_osu(&self.beatmap, &audio_filename, self.song_info.metadata.preview_start)?;
+        let audio = path
+            .parent()
+            .ok_or(anyhow!("No parent for {:?}", path))?
+            .join(&audio_filename);
+        if !audio.exists() {
+            std::fs::copy(bgm_file, audio)?;
+        }
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+/// Edit the time that can be unset, the button sets it to the current time.
+fn edit_optional_time(ui: &mut Ui, time: &mut Option<OffsetType>, current_time: OffsetType) -> bool {
+    let mut changed = false;
+    let mut enabled = time.is_some();
+    if ui.checkbox(&mut enabled, "").changed() {
+        *time = enabled.then_some(current_time);
+        changed = true;
+    }
+    if let Some(time) = time {
+        changed |= DragValue::new(time).suffix("ms").ui(ui).changed();
+        if ui.button("Now | 当前").clicked() {
+            *time = current_time;
+            changed = true;
+        }
     }
-}
\ No newline at end of file
+    changed
+}
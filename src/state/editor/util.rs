@@ -1,10 +1,14 @@
+use crate::game::beatmap::osu::OSU_EXT;
+use crate::game::beatmap::sm::SM_EXTS;
+use crate::game::package::PACKAGE_EXT;
+use crate::game::song::SongInfo;
 use egui::{Pos2, Rect};
 use std::path::PathBuf;
 use winit::window::Window;
 
 pub fn select_music_file(window: &Window) -> Option<PathBuf> {
     let result = rfd::FileDialog::new()
-        .add_filter("music", &["mp3", "ogg"])
+        .add_filter("music", SongInfo::supported_bgm_format())
         .set_directory("/")
         .set_parent(window)
         .pick_file();
@@ -14,6 +18,54 @@ pub fn select_music_file(window: &Window) -> Option<PathBuf> {
     result
 }
 
+pub fn select_image_file(window: &Window) -> Option<PathBuf> {
+    let result = rfd::FileDialog::new()
+        .add_filter("image", &["png", "jpg", "jpeg"])
+        .set_directory("/")
+        .set_parent(window)
+        .pick_file();
+
+    log::info!("Select image result: {result:?}");
+
+    result
+}
+
+pub fn select_chart_file(window: &Window) -> Option<PathBuf> {
+    let result = rfd::FileDialog::new()
+        .add_filter("chart", &[OSU_EXT, SM_EXTS[0], SM_EXTS[1], PACKAGE_EXT])
+        .set_directory("/")
+        .set_parent(window)
+        .pick_file();
+
+    log::info!("Select chart result: {result:?}");
+
+    result
+}
+
+pub fn select_export_osu_file(window: &Window, file_name: &str) -> Option<PathBuf> {
+    let result = rfd::FileDialog::new()
+        .add_filter("osu!", &[OSU_EXT])
+        .set_file_name(file_name)
+        .set_parent(window)
+        .save_file();
+
+    log::info!("Select export file result: {result:?}");
+
+    result
+}
+
+pub fn select_export_package_file(window: &Window, file_name: &str) -> Option<PathBuf> {
+    let result = rfd::FileDialog::new()
+        .add_filter("package", &[PACKAGE_EXT])
+        .set_file_name(file_name)
+        .set_parent(window)
+        .save_file();
+
+    log::info!("Select export package result: {result:?}");
+
+    result
+}
+
 pub fn map_point_to_std_pos_in_rect(rect: &Rect, pos: Pos2) -> (f32, f32) {
     let x = (pos.x - rect.center().x) * 2.0 / rect.width();
     let y = (rect.center().y - pos.y) * 2.0 / rect.height();
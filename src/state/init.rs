@@ -5,6 +5,7 @@ use std::sync::Arc;
 use crate::engine::atlas::TextureAtlas;
 use crate::engine::global::{INITED, IO_POOL, STATIC_DATA};
 use crate::engine::renderer::texture_renderer::TextureRenderer;
+use crate::engine::task::wakers::WindowWaker;
 use crate::engine::{GameState, LoopState, ResourceLocation, ResourceManager, StateData, StateEvent, Trans, WaitFutureState, WaitResult};
 use crate::game::song::SongManager;
 use futures::task::SpawnExt;
@@ -80,7 +81,12 @@ impl GameState for InitState {
                     
                     WaitResult::Function(Box::new(|s| {
                         s.app.egui_ctx.set_fonts(STATIC_DATA.font.clone());
-                        s.wd.world.insert(Arc::new(song_manager));
+                        let song_manager = Arc::new(song_manager);
+                        let waker = WindowWaker::new(s.wd.elp.clone(), &s.app.window);
+                        if let Err(e) = song_manager.start_watch(waker.into()) {
+                            log::warn!("Failed to watch the songs for {:?}", e);
+                        }
+                        s.wd.world.insert(song_manager);
                         let gpu = s.app.gpu.as_ref().unwrap();
                         let tr = s.app.world.get_mut::<TextureRenderer>()
                             .unwrap();
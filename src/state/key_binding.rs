@@ -0,0 +1,137 @@
+use crate::engine::global::STATIC_DATA;
+use crate::engine::{GameState, LoopState, StateData, StateEvent, Trans};
+use crate::game::beatmap::{MAX_KEYS, MIN_KEYS};
+use crate::game::key_binding::{
+    get_default_binding, get_key_label, load_binding, save_binding, BINDABLE_KEYS,
+};
+use egui::{Button, Context, Frame, RichText, Widget};
+use winit::event::WindowEvent;
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+/// Bind the keys of the lanes for every key count.
+pub struct KeyBindingState {
+    keys: u8,
+    binding: Vec<KeyCode>,
+    /// The lane waiting for the key.
+    capturing: Option<usize>,
+}
+
+impl KeyBindingState {
+    pub fn new() -> Self {
+        let mut this = Self {
+            keys: MIN_KEYS,
+            binding: vec![],
+            capturing: None,
+        };
+        this.load(MIN_KEYS);
+        this
+    }
+
+    fn load(&mut self, keys: u8) {
+        self.keys = keys;
+        self.capturing = None;
+        self.binding = match STATIC_DATA.cfg_data.write() {
+            Ok(mut cfg) => load_binding(&mut cfg, keys),
+            Err(e) => {
+                log::warn!("Cannot write lock for {:?}", e);
+                get_default_binding(keys)
+            }
+        };
+    }
+
+    fn save(&self) {
+        match STATIC_DATA.cfg_data.write() {
+            Ok(mut cfg) => save_binding(&mut cfg, self.keys, &self.binding),
+            Err(e) => log::warn!("Cannot write lock for {:?}", e),
+        }
+    }
+
+    /// Bind the key to the lane, the lane had the key before gets the old key of the lane.
+    fn bind(&mut self, lane: usize, code: KeyCode) {
+        if let Some(other) = self.binding.iter().position(|x| *x == code) {
+            self.binding.swap(lane, other);
+        } else {
+            self.binding[lane] = code;
+        }
+        self.save();
+    }
+}
+
+impl GameState for KeyBindingState {
+    fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
+        let mut trans = Trans::None;
+        if s.app.inputs.is_pressed(&[PhysicalKey::Code(KeyCode::Escape)]) {
+            if self.capturing.is_some() {
+                self.capturing = None;
+            } else {
+                trans = Trans::Pop;
+            }
+        }
+        (trans, LoopState::WAIT)
+    }
+
+    fn render(&mut self, _: &mut StateData, ctx: &Context) -> Trans {
+        let mut trans = Trans::None;
+        egui::CentralPanel::default()
+            .frame(Frame::NONE)
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.heading("Key binding | 按键");
+                    ui.horizontal(|ui| {
+                        for keys in MIN_KEYS..=MAX_KEYS {
+                            if ui.selectable_label(self.keys == keys, format!("{}K", keys)).clicked()
+                                && self.keys != keys
+                            {
+                                self.load(keys);
+                            }
+                        }
+                    });
+                    ui.add_space(20.0);
+                    ui.horizontal(|ui| {
+                        for lane in 0..self.binding.len() {
+                            let text = if self.capturing == Some(lane) {
+                                RichText::new("...").strong()
+                            } else {
+                                RichText::new(get_key_label(self.binding[lane]))
+                            };
+                            if Button::new(text).min_size((80.0, 80.0).into()).ui(ui).clicked() {
+                                self.capturing = Some(lane);
+                            }
+                        }
+                    });
+                    ui.add_space(20.0);
+                    if self.capturing.is_some() {
+                        ui.label("Press the key for the lane, Esc to cancel | 按下按键, Esc 取消");
+                    } else {
+                        ui.label("Click the lane to bind | 点击轨道以绑定");
+                    }
+                    if ui.button("Reset | 重置").clicked() {
+                        self.binding = get_default_binding(self.keys);
+                        self.capturing = None;
+                        self.save();
+                    }
+                    if ui.button("Back | 返回").clicked() {
+                        trans = Trans::Pop;
+                    }
+                });
+            });
+        trans
+    }
+
+    fn on_event(&mut self, _: &mut StateData, event: StateEvent) {
+        if let StateEvent::Window(WindowEvent::KeyboardInput { event, .. }, _) = event {
+            let Some(lane) = self.capturing else {
+                return;
+            };
+            if !event.state.is_pressed() || event.repeat {
+                return;
+            }
+            if let PhysicalKey::Code(code) = event.physical_key {
+                if BINDABLE_KEYS.contains(&code) {
+                    self.bind(lane, code);
+                    self.capturing = None;
+                }
+            }
+        }
+    }
+}
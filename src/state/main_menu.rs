@@ -3,6 +3,7 @@ use crate::engine::{GameState, LoopState, StateData, StateEvent, Trans};
 use crate::state::editor::EditorMenu;
 use egui::{Button, Context, Frame, Widget};
 use winit::keyboard::{KeyCode, PhysicalKey};
+use crate::state::key_binding::KeyBindingState;
 use crate::state::play::PlayMenu;
 
 pub struct MenuState {
@@ -47,7 +48,7 @@ impl GameState for MenuState {
                     let button_height = 100.0f32;
                     let padding = ui.style().spacing.button_padding.y;
 
-                    let button_num = 2f32;
+                    let button_num = 3f32;
                     let total_height = button_height * button_num + (button_num - 1f32) * padding;
 
                     ui.allocate_space((0.0, (height - total_height).max(0.0) / 2.0).into());
@@ -59,6 +60,10 @@ impl GameState for MenuState {
                     if Button::new("Editor").min_size((200.0, 100.0).into()).ui(ui).clicked() {
                         tran = Trans::Push(Box::new(EditorMenu::new()));
                     }
+
+                    if Button::new("Keys").min_size((200.0, 100.0).into()).ui(ui).clicked() {
+                        tran = Trans::Push(Box::new(KeyBindingState::new()));
+                    }
                 });
             });
 
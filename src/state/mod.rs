@@ -3,5 +3,6 @@ pub use main_menu::*;
 
 mod init;
 mod main_menu;
+mod key_binding;
 mod editor;
 mod play;
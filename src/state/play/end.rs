@@ -1,15 +1,69 @@
-use crate::engine::{GameState, LoopState, StateData, Trans};
-use crate::game::beatmap::play::{Gaming, NoteResult};
-use crate::game::beatmap::summary::{BeatmapPlayResult, HitSummary};
+use crate::engine::{GameState, LoopState, StateData, Trans, WaitFutureState, WaitResult};
+use crate::game::beatmap::play::{Gaming, JudgePart, NoteResult};
+use crate::game::beatmap::health::MAX_HEALTH;
+use crate::game::beatmap::summary::BeatmapPlayResult;
+use crate::game::beatmap::SongBeatmapInfo;
+use crate::game::replay::Replay;
+use crate::game::song::SongInfo;
+use crate::state::play::gaming::GamingState;
 use egui::{
-    Align, Color32, Context, Frame, Label, Layout, Pos2, Rect, RichText, Stroke, TextWrapMode,
-    UiBuilder,
+    Align, Color32, Context, Frame, Label, Layout, Pos2, Rect, RichText, Sense, Shape, Stroke,
+    StrokeKind, TextWrapMode, Ui, UiBuilder, Vec2,
 };
+use std::sync::Arc;
 use winit::keyboard::{KeyCode, PhysicalKey};
 
 pub struct EndResultState {
     pub result: BeatmapPlayResult,
     pub gaming: Box<Gaming>,
+    pub song_info: Arc<SongInfo>,
+    pub beatmap: SongBeatmapInfo,
+    /// The replay of the play, none if failed to record.
+    pub replay: Option<Replay>,
+}
+
+/// Plot the health over the time from 0 to the end of the beatmap.
+fn health_ui(ui: &mut Ui, result: &BeatmapPlayResult) {
+    let (rect, _) = ui.allocate_exact_size(Vec2::new(400.0, 80.0), Sense::hover());
+    ui.painter()
+        .rect_stroke(rect, 0.0, Stroke::new(1.0, Color32::GRAY), StrokeKind::Inside);
+    let end_time = result
+        .health_history
+        .last()
+        .map_or(result.end_time, |x| x.0.max(result.end_time))
+        .max(1);
+    let points = result
+        .health_history
+        .iter()
+        .map(|(time, health)| {
+            Pos2::new(
+                rect.left() + rect.width() * *time as f32 / end_time as f32,
+                rect.bottom() - rect.height() * health / MAX_HEALTH,
+            )
+        })
+        .collect::<Vec<_>>();
+    let color = if result.failed { Color32::RED } else { Color32::GREEN };
+    ui.painter().add(Shape::line(points, Stroke::new(2.0, color)));
+}
+
+impl EndResultState {
+    fn watch_replay(&self, s: &mut StateData) -> Trans {
+        let Some(replay) = self.replay.clone() else {
+            return Trans::None;
+        };
+        let handle = s.app.audio.as_mut().unwrap().stream_handle.clone();
+        let song_info = self.song_info.clone();
+        let beatmap = self.beatmap.clone();
+        Trans::Switch(WaitFutureState::wait_task(async move {
+            match GamingState::new_replay(handle, song_info, beatmap, replay) {
+                Ok(state) => WaitResult::Switch(Box::new(state)),
+                Err(e) => {
+                    log::warn!("Failed to play back the replay for {:?}", e);
+                    WaitResult::Pop
+                }
+            }
+        }))
+    }
 }
 
 impl GameState for EndResultState {
@@ -25,6 +79,8 @@ impl GameState for EndResultState {
             .contains(&PhysicalKey::Code(KeyCode::Escape))
         {
             trans = Trans::Pop;
+        } else if s.app.inputs.is_pressed(&[PhysicalKey::Code(KeyCode::KeyR)]) {
+            trans = self.watch_replay(s);
         }
         (trans, LoopState::WAIT)
     }
@@ -43,30 +99,52 @@ impl GameState for EndResultState {
                             .size(99.0),
                     );
                     ui.heading(
-                        RichText::new(self.result.score.to_string())
+                        RichText::new(self.result.mod_score.to_string())
                             .strong()
                             .size(50.0),
                     );
+                    if self.result.failed {
+                        ui.heading(RichText::new("Failed").strong().color(Color32::RED));
+                    }
+                    let mods = self.result.mods.get_names();
+                    if !mods.is_empty() {
+                        ui.label(format!(
+                            "{} (x{:.2})",
+                            mods.join(", "),
+                            self.result.multiplier
+                        ));
+                    }
                 });
 
                 ui.vertical(|ui| {
-                    macro_rules! label_result {
-                        ($prefix: literal, $name: ident) => {
-                            ui.label(
-                                RichText::new(format!(
-                                    "{}: {}",
-                                    $prefix,
-                                    self.gaming.score_counter.get_note_count(NoteResult::$name)
-                                ))
-                                .strong(),
-                            );
-                        };
-                    }
-                    label_result!("Perfect", Perfect);
-                    label_result!("Great", Great);
-                    label_result!("Good", Good);
-                    label_result!("Bad", Bad);
-                    label_result!("Miss", Miss);
+                    let counter = &self.gaming.score_counter;
+                    // break out the head and tail of the long notes.
+                    let has_long = !self.gaming.raw_file.long_notes.is_empty();
+                    egui::Grid::new("result_counts").show(ui, |ui| {
+                        if has_long {
+                            ui.label("");
+                            for name in ["Total", "Note", "Head", "Tail"] {
+                                ui.label(RichText::new(name).strong());
+                            }
+                            ui.end_row();
+                        }
+                        for result in [
+                            NoteResult::Perfect,
+                            NoteResult::Great,
+                            NoteResult::Good,
+                            NoteResult::Bad,
+                            NoteResult::Miss,
+                        ] {
+                            ui.label(RichText::new(format!("{:?}:", result)).strong());
+                            ui.label(RichText::new(counter.get_note_count(result).to_string()).strong());
+                            if has_long {
+                                for part in JudgePart::ALL {
+                                    ui.label(counter.get_part_count(part, result).to_string());
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
 
                     ui.label(
                         RichText::new(format!(
@@ -75,6 +153,11 @@ impl GameState for EndResultState {
                         ))
                         .strong(),
                     );
+                    if self.replay.is_some() && ui.button("Watch replay | 回放 (R)").clicked() {
+                        trans = self.watch_replay(s);
+                    }
+                    ui.label("Health | 生命");
+                    health_ui(ui, &self.result);
 
                     let bottom_graph_rect = {
                         let bottom_graph_rect = ui.available_rect_before_wrap();
@@ -108,7 +191,7 @@ impl GameState for EndResultState {
                         );
 
                         let bar_height = bar_base_y - graph_top;
-                        for (idx, number) in HitSummary::bottom_numbers().enumerate() {
+                        for (idx, number) in self.result.hit_summary.bottom_numbers().enumerate() {
                             let text_rect = Rect::from_min_max(
                                 Pos2::new(
                                     start_offset + idx as f32 * cell_width,
@@ -161,9 +244,10 @@ impl GameState for EndResultState {
                                 );
                             }
 
-                            // < 0 require 0
-                            // > 0 require 1
-                            if idx & 1 == if number < 0 { 0 } else { 1 } {
+                            // every other number from the center bin.
+                            let center = self.result.hit_summary.delay_count.len() / 2;
+                            let from_center = if number < 0 { center - idx } else { idx - center - 1 };
+                            if from_center & 1 == 0 {
                                 ui.allocate_new_ui(UiBuilder::new().max_rect(text_rect), |ui| {
                                     ui.centered_and_justified(|ui| {
                                         ui.add_sized(
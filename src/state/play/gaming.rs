@@ -1,27 +1,39 @@
-use crate::engine::global::STATIC_DATA;
+use crate::engine::global::{IO_POOL, STATIC_DATA};
 use crate::engine::renderer::texture_renderer::TextureRenderer;
 use crate::engine::sources::ControlledBufferHandle;
 use crate::engine::{EasyGuiExt, GameState, LoopState, OutputStreamHandle, ResourceLocation, StateData, StateEvent, Trans};
-use crate::game::beatmap::file::SongBeatmapFile;
-use crate::game::beatmap::play::{Gaming, NoteHitResult, NoteResult, PlayingNoteType};
+use crate::game::beatmap::health::MAX_HEALTH;
+use crate::game::beatmap::mods::{PlayMods, HIDDEN_COVER, SUDDEN_COVER};
+use crate::game::beatmap::play::{
+    Gaming, JudgePart, JudgePreset, NoteHitResult, NoteResult, PlayOptions,
+    NoteJudgement, JUDGE_PRESET_KEY,
+};
 use crate::game::beatmap::summary::BeatmapPlayResult;
-use crate::game::beatmap::{GamePos, FOUR_KEY_X};
+use crate::game::beatmap::{get_lanes_x, GamePos, SongBeatmapInfo};
+use crate::game::key_binding::load_binding;
 use crate::game::render::NoteRenderer;
+use crate::game::replay::{get_replay_dir, Replay, ReplayInput, ReplayInputKind, ReplayPlayer};
 use crate::game::song::SongInfo;
-use crate::game::{get_play_rect, secs_to_offset_type, GameTimeType, OffsetType};
+use crate::game::{
+    get_play_rect, map_screen_x_to_game_x, offset_type_to_secs, secs_to_offset_type, GameTimeType,
+    OffsetType,
+};
 use crate::state::play::end::EndResultState;
 use anyhow::anyhow;
 use egui::{
-    Align, Color32, Context, Frame, Layout, Pos2, Rect, RichText, Stroke, TextStyle, Vec2, Widget,
+    Align, Align2, Color32, Context, FontId, Frame, Layout, Mesh, Order, Painter, Pos2, Rect,
+    RichText, Stroke, StrokeKind, TextStyle, Vec2, Widget,
 };
 use rodio::buffer::SamplesBuffer;
 use rodio::{Decoder, Sink, Source};
 use std::io::{Cursor, Read};
 use std::ops::{Add, Deref};
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::Instant;
 use winit::dpi::PhysicalSize;
-use winit::event::WindowEvent;
+use winit::event::{TouchPhase, WindowEvent};
 use winit::keyboard::{KeyCode, PhysicalKey};
 
 #[derive(Default)]
@@ -75,6 +87,54 @@ pub struct GamingState {
     sink: ControlledBufferHandle,
     score_display: ScoreDisplay,
     end_remaining: Option<f32>,
+    /// The secs left to slow down the audio after failed.
+    fail_remaining: Option<f32>,
+    /// The game time paused at, the pause menu is shown until resumed.
+    paused_time: Option<GameTimeType>,
+    /// The game time to judge again after resumed, the countdown is shown before it.
+    resume_time: Option<GameTimeType>,
+    bgm_vol: f32,
+    /// The secs the notes are delayed against the audio.
+    audio_offset: GameTimeType,
+    /// The audio playback rate of the mods.
+    rate: GameTimeType,
+    song_info: Arc<SongInfo>,
+    beatmap: SongBeatmapInfo,
+    /// Play back the replay rather than taking the inputs.
+    replay: Option<ReplayPlayer>,
+    /// The x of the lanes and the keys bound to them.
+    lanes_x: Vec<f32>,
+    binding: Vec<KeyCode>,
+}
+
+/// The pointer of the key is the lane with this bit, it never conflicts with the
+/// [`crate::engine::Pointer`] ids of the mouse and the touch.
+const KEY_POINTER: u64 = 1 << 63;
+
+/// The secs to slow down the audio after failed, and the slowest speed.
+const FAIL_SECS: f32 = 3.0;
+const FAIL_SPEED: f32 = 0.25;
+/// The secs rewound for the countdown when resumed.
+const COUNTDOWN_SECS: f32 = 3.0;
+
+/// Cover the notes from the `edge` to the `solid` y and fade out to the `clear` y, the y is from
+/// the judgement line (0) to the top (1) of the play area.
+fn paint_cover(painter: &Painter, game_rect: Rect, edge: f32, (solid, clear): (f32, f32)) {
+    let to_y = |y: f32| game_rect.center().y - y * (game_rect.center().y - game_rect.top());
+    let (left, right) = (game_rect.left(), game_rect.right());
+    painter.rect_filled(
+        Rect::from_two_pos(Pos2::new(left, to_y(edge)), Pos2::new(right, to_y(solid))),
+        0.0,
+        Color32::BLACK,
+    );
+    let mut mesh = Mesh::default();
+    for (y, color) in [(solid, Color32::BLACK), (clear, Color32::TRANSPARENT)] {
+        mesh.colored_vertex(Pos2::new(left, to_y(y)), color);
+        mesh.colored_vertex(Pos2::new(right, to_y(y)), color);
+    }
+    mesh.add_triangle(0, 1, 2);
+    mesh.add_triangle(1, 2, 3);
+    painter.add(mesh);
 }
 
 impl GamingState {
@@ -82,13 +142,50 @@ impl GamingState {
         if self.sink.is_stopped() {
             return self.total_duration.as_secs_f64();
         }
-        self.sink.get_pos().as_secs_f64() - 3.0
+        // the audio is played faster with the rate, so is the game.
+        self.sink.get_pos().as_secs_f64() * self.rate - 3.0 - self.audio_offset
     }
 
     pub fn new(
         handle: OutputStreamHandle,
-        song_info: &SongInfo,
-        beatmap_file: SongBeatmapFile,
+        song_info: Arc<SongInfo>,
+        beatmap: SongBeatmapInfo,
+        mods: PlayMods,
+    ) -> anyhow::Result<Self> {
+        let beatmap_file = beatmap.load_beatmap()?;
+        let ops = {
+            let cfg = STATIC_DATA
+                .cfg_data
+                .read()
+                .map_err(|e| anyhow!("Cannot read lock for {:?}", e))?;
+            PlayOptions {
+                judge_preset: cfg.get_str(JUDGE_PRESET_KEY).and_then(JudgePreset::from_name),
+                mods,
+                ..Default::default()
+            }
+        };
+        let gaming = Gaming::load_game(beatmap_file, ops);
+        Self::with_gaming(handle, song_info, beatmap, gaming, None)
+    }
+
+    /// Play back the replay of the beatmap.
+    pub fn new_replay(
+        handle: OutputStreamHandle,
+        song_info: Arc<SongInfo>,
+        beatmap: SongBeatmapInfo,
+        replay: Replay,
+    ) -> anyhow::Result<Self> {
+        let gaming = replay.load_game(beatmap.load_beatmap()?)?;
+        let player = ReplayPlayer::new(replay);
+        Self::with_gaming(handle, song_info, beatmap, gaming, Some(player))
+    }
+
+    fn with_gaming(
+        handle: OutputStreamHandle,
+        song_info: Arc<SongInfo>,
+        beatmap: SongBeatmapInfo,
+        gaming: Gaming,
+        replay: Option<ReplayPlayer>,
     ) -> anyhow::Result<Self> {
         let mut buf = vec![];
         let mut file = std::fs::File::open(&song_info.bgm_file)?;
@@ -113,28 +210,120 @@ impl GamingState {
         let rate = samples.sample_rate();
         buffer_data.append(&mut samples.collect::<Vec<f32>>());
 
-        let vol = STATIC_DATA
-            .cfg_data
-            .write()
-            .map_err(|e| anyhow!("Cannot read lock for {:?}", e))?
-            .get_f32_def("bgm_vol", 1.0);
-        let mut sink =
-            ControlledBufferHandle::new(&handle, SamplesBuffer::new(channels, rate, buffer_data))?;
+        // the falling beatmap is played in 4 lanes.
+        let keys = gaming.raw_file.rule.get_keys().unwrap_or(4);
+        let (vol, binding) = {
+            let mut cfg = STATIC_DATA
+                .cfg_data
+                .write()
+                .map_err(|e| anyhow!("Cannot read lock for {:?}", e))?;
+            (cfg.get_f32_def("bgm_vol", 1.0), load_binding(&mut cfg, keys))
+        };
+        // play the samples faster or slower for the rate, the pitch changes with it.
+        let play_rate = gaming.ops.mods.get_rate();
+        let sample_rate = (rate as f32 * play_rate).round() as u32;
+        let mut sink = ControlledBufferHandle::new(
+            &handle,
+            SamplesBuffer::new(channels, sample_rate, buffer_data),
+        )?;
         sink.set_volume(vol);
 
         let this = Self {
             total_duration,
             start_time: Instant::now(),
             hit_feedback: Default::default(),
-            gaming: Box::new(Gaming::load_game(beatmap_file)),
+            gaming: Box::new(gaming),
             game_rect: Rect::ZERO,
             sink,
             score_display: Default::default(),
             end_remaining: None,
+            fail_remaining: None,
+            paused_time: None,
+            resume_time: None,
+            bgm_vol: vol,
+            audio_offset: offset_type_to_secs(song_info.metadata.audio_offset),
+            rate: play_rate as GameTimeType,
+            song_info,
+            beatmap,
+            replay,
+            lanes_x: get_lanes_x(keys),
+            binding,
         };
         Ok(this)
     }
 
+    /// Send the pointer input happened at the `time` to the game.
+    fn process_pointer(
+        &mut self,
+        s: &mut StateData,
+        kind: ReplayInputKind,
+        x: f32,
+        pointer: u64,
+        time: std::time::Instant,
+    ) {
+        let input_game_time = self.get_game_time() - time.elapsed().as_secs_f64() * self.rate;
+        let input = ReplayInput::new(kind, GamePos::new(x, secs_to_offset_type(input_game_time)), pointer);
+        if let Some(judgement) = input.apply(&mut self.gaming) {
+            self.hit_feedback.last_result = Some((judgement.result, Instant::now()));
+            if !judgement.result.is_miss() && judgement.part != JudgePart::Tail {
+                let tick_sound_res: ResourceLocation = ResourceLocation::from_name("tick");
+                s.app.audio.as_mut().unwrap().play_sfx(&tick_sound_res);
+            }
+        }
+    }
+
+    /// Whether the inputs are judged, not when paused, counting down or failed.
+    fn is_judging(&self) -> bool {
+        self.paused_time.is_none() && self.resume_time.is_none() && !self.gaming.health.is_failed()
+    }
+
+    fn pause(&mut self) {
+        // pausing in the countdown keeps the first paused time.
+        let time = self.resume_time.take().unwrap_or_else(|| self.get_game_time());
+        self.sink.pause();
+        // the releases in the pause are not sent, so release the pointers now.
+        if self.replay.is_none() {
+            self.gaming.release_all(secs_to_offset_type(time));
+        }
+        self.paused_time = Some(time);
+    }
+
+    /// Rewind for the countdown and play, the judgement starts again from the paused time.
+    fn resume(&mut self) {
+        let Some(time) = self.paused_time.take() else {
+            return;
+        };
+        let pos = self
+            .sink
+            .get_pos()
+            .saturating_sub(Duration::from_secs_f32(COUNTDOWN_SECS));
+        self.sink.seek_to(pos);
+        self.sink.play();
+        self.resume_time = Some(time);
+    }
+
+    /// Play again from the start with the same beatmap and audio.
+    fn restart(&mut self) {
+        self.gaming = Box::new(Gaming::load_game(
+            self.gaming.raw_file.clone(),
+            self.gaming.ops,
+        ));
+        if let Some(replay) = &mut self.replay {
+            replay.restart();
+        }
+        self.sink.seek_to(Duration::ZERO);
+        self.sink.set_speed(1.0);
+        self.sink.set_volume(self.bgm_vol);
+        self.sink.play();
+        self.start_time = Instant::now();
+        self.hit_feedback = Default::default();
+        self.score_display = Default::default();
+        self.end_remaining = None;
+        self.fail_remaining = None;
+        self.paused_time = None;
+        self.resume_time = None;
+    }
+
     fn update_game_region(&mut self, size: PhysicalSize<u32>) {
         // we are 4:3 game
         self.game_rect = get_play_rect(Rect::from_min_max(
@@ -158,17 +347,30 @@ impl GameState for GamingState {
     fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
         let mut trans = Trans::None;
 
-        let cur_input = &s.app.inputs.cur_frame_input;
-        if cur_input
-            .pressing
-            .contains(&PhysicalKey::Code(KeyCode::Escape))
-        {
-            trans = Trans::Pop;
+        if s.app.inputs.is_pressed(&[PhysicalKey::Code(KeyCode::Escape)]) {
+            if self.paused_time.is_some() {
+                self.resume();
+            } else if self.end_remaining.is_none() && self.fail_remaining.is_none() {
+                self.pause();
+            } else {
+                trans = Trans::Pop;
+            }
         }
-        if s.app.inputs.is_pressed(&[PhysicalKey::Code(KeyCode::Tab)]) {
-            self.gaming.auto_play = !self.gaming.auto_play;
+        if let Some(x) = &mut self.fail_remaining {
+            *x -= s.dt;
+            if *x <= 0.0 {
+                trans = Trans::IntoSwitch;
+            }
+            let left = (*x / FAIL_SECS).max(0.0);
+            self.sink.set_speed(FAIL_SPEED + (1.0 - FAIL_SPEED) * left);
+            self.sink
+                .set_volume(0.0_f32.max(self.sink.volume() - s.dt / FAIL_SECS));
+        } else if self.gaming.health.is_failed() {
+            self.fail_remaining = Some(FAIL_SECS);
+            self.end_remaining = None;
         }
         match &mut self.end_remaining {
+            _ if self.fail_remaining.is_some() => {}
             Some(x) => {
                 *x -= s.dt;
                 if (*x <= 0.0) {
@@ -196,37 +398,38 @@ impl GameState for GamingState {
             log::trace!(target: "Gameplay", "{} when {} (delta: {})", game_time, elapsed, elapsed - game_time);
         }
         let tick_sound_res: ResourceLocation = ResourceLocation::from_name("tick");
-        self.gaming.tick(
-            game_time,
-            Some(|note: PlayingNoteType<'_>, result: NoteHitResult| {
-                // auto play will play during tick.
-                if result.is_miss() {
-                    // The miss we should care.
+        if self.resume_time.is_some_and(|x| game_time >= x) {
+            self.resume_time = None;
+        }
+        let judging = self.is_judging();
+        if let Some(replay) = self.replay.as_mut().filter(|_| judging) {
+            replay.feed(&mut self.gaming, secs_to_offset_type(game_time), |result| {
+                self.hit_feedback.last_result = Some((result, Instant::now()));
+                if !result.is_miss() {
+                    s.app.audio.as_mut().unwrap().play_sfx(&tick_sound_res);
+                }
+            });
+        }
+        if judging {
+            self.gaming.tick(
+                game_time,
+                Some(|judgement: NoteJudgement| {
+                    // auto play will play during tick.
+                    let result = judgement.result;
                     self.hit_feedback.last_result = Some((result, Instant::now()));
-                } else {
-                    match note {
-                        PlayingNoteType::Normal(_) => {
-                            self.hit_feedback.last_result = Some((result, Instant::now()));
-                            s.app.audio.as_mut().unwrap().play_sfx(&tick_sound_res);
-                        }
-                        PlayingNoteType::Long(note) => {
-                            if note.start_result.is_none() {
-                                s.app.audio.as_mut().unwrap().play_sfx(&tick_sound_res);
-                            } else {
-                                // we ignore the end result of long note.
-                            }
-                        }
+                    if !result.is_miss() && judgement.part != JudgePart::Tail {
+                        s.app.audio.as_mut().unwrap().play_sfx(&tick_sound_res);
                     }
-                }
-            }),
-        );
+                }),
+            );
+        }
         let gpu = s.app.gpu.as_mut().unwrap();
         let mut nr = s.app.world.fetch_mut::<NoteRenderer>();
         for (timing_group, x) in self.gaming.normal_notes.iter().enumerate() {
             let current_y = self.gaming.raw_file.timing_group.get_gameplay_y_game_time(
                 game_time,
                 timing_group as u8,
-                self.gaming.ops.default_view_time,
+                self.gaming.ops.get_view_time(),
             );
             let (normal_a, normal_b) = x.get_play_notes().as_slices();
             nr.collect_playing_notes(normal_a, gpu.get_screen_size_f32(), current_y);
@@ -236,7 +439,7 @@ impl GameState for GamingState {
             let current_y = self.gaming.raw_file.timing_group.get_gameplay_y_game_time(
                 game_time,
                 timing_group as u8,
-                self.gaming.ops.default_view_time,
+                self.gaming.ops.get_view_time(),
             );
             let (normal_a, normal_b) = x.get_play_notes().as_slices();
             nr.collect_playing_notes(normal_a, gpu.get_screen_size_f32(), current_y);
@@ -273,6 +476,46 @@ impl GameState for GamingState {
                     }
                 });
 
+                let mods = &self.gaming.ops.mods;
+                if mods.hidden {
+                    paint_cover(ui.painter(), game_rect, 0.0, HIDDEN_COVER);
+                }
+                if mods.sudden {
+                    paint_cover(ui.painter(), game_rect, 1.0, SUDDEN_COVER);
+                }
+
+                // the health bar at the left of the play area, from the center line up.
+                let health = self.gaming.health.get_health();
+                let bar_rect = Rect::from_min_max(
+                    Pos2::new(game_rect.left() - 30.0, game_rect.top()),
+                    Pos2::new(game_rect.left() - 15.0, game_rect.center().y),
+                );
+                ui.painter().rect_stroke(bar_rect, 0.0, Stroke::new(1.0, Color32::GRAY), StrokeKind::Outside);
+                let mut filled = bar_rect;
+                filled.set_top(bar_rect.bottom() - bar_rect.height() * health / MAX_HEALTH);
+                let color = if health < 0.3 { Color32::RED } else { Color32::GREEN };
+                ui.painter().rect_filled(filled, 0.0, color);
+                if self.fail_remaining.is_some() {
+                    ui.painter().text(
+                        game_rect.center(),
+                        Align2::CENTER_BOTTOM,
+                        "Failed",
+                        FontId::proportional(99.0),
+                        Color32::RED,
+                    );
+                }
+                if let Some(resume_time) = self.resume_time {
+                    // the real secs left.
+                    let left = ((resume_time - game_time) / self.rate).ceil().max(1.0);
+                    ui.painter().text(
+                        game_rect.center(),
+                        Align2::CENTER_BOTTOM,
+                        format!("{}", left as u32),
+                        FontId::proportional(99.0),
+                        Color32::WHITE,
+                    );
+                }
+
                 // the center line
                 ui.painter().hline(
                     ui.max_rect().x_range(),
@@ -297,11 +540,35 @@ impl GameState for GamingState {
                     }
                 });
             });
+
+        if self.paused_time.is_some() {
+            egui::Window::new("Paused | 暂停")
+                .order(Order::TOP)
+                .collapsible(false)
+                .resizable(false)
+                .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+                .show(ctx, |ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        if ui.button("Resume | 继续 (Esc)").clicked() {
+                            self.resume();
+                        }
+                        if ui.button("Restart | 重新开始").clicked() {
+                            self.restart();
+                        }
+                        if ui.button("Quit | 退出").clicked() {
+                            trans = Trans::Pop;
+                        }
+                    });
+                });
+        }
         trans
     }
 
     fn on_event(&mut self, s: &mut StateData, event: StateEvent) {
         match event {
+            StateEvent::Window(WindowEvent::Resized(size), _) => self.update_game_region(*size),
+            // the replay takes no inputs, and nothing is judged when paused or failed.
+            StateEvent::Window(_, _) if self.replay.is_some() || !self.is_judging() => {}
             StateEvent::Window(event, time) => match event {
                 WindowEvent::KeyboardInput {
                     event,
@@ -311,57 +578,79 @@ impl GameState for GamingState {
                     if *is_synthetic || event.repeat {
                         return;
                     }
-                    match event.physical_key {
-                        PhysicalKey::Code(code) => match code {
-                            _ => {
-                                let input_game_time =
-                                    self.get_game_time() - time.elapsed().as_secs_f64();
-
-                                let input_x = match code {
-                                    KeyCode::KeyD => FOUR_KEY_X[0],
-                                    KeyCode::KeyF => FOUR_KEY_X[1],
-                                    KeyCode::KeyJ => FOUR_KEY_X[2],
-                                    KeyCode::KeyK => FOUR_KEY_X[3],
-                                    _ => return,
-                                };
-                                let game_input =
-                                    GamePos::new(input_x, secs_to_offset_type(input_game_time));
-
-                                if event.state.is_pressed() {
-                                    if let Some((result, is_long)) = self
-                                        .gaming
-                                        .process_input(game_input, ((input_x + 0.75) * 4.0) as _)
-                                    {
-                                        self.hit_feedback.last_result =
-                                            Some((result, Instant::now()));
-                                        if !result.is_miss() {
-                                            let tick_sound_res: ResourceLocation =
-                                                ResourceLocation::from_name("tick");
-                                            s.app.audio.as_mut().unwrap().play_sfx(&tick_sound_res);
-                                        }
-                                    }
-                                } else {
-                                    self.gaming.process_input_leave(
-                                        game_input,
-                                        ((input_x + 0.75) * 4.0) as _,
-                                    );
-                                }
-                            }
-                        },
-                        PhysicalKey::Unidentified(_) => {}
-                    }
+                    let PhysicalKey::Code(code) = event.physical_key else {
+                        return;
+                    };
+                    let Some(lane) = self.binding.iter().position(|x| *x == code) else {
+                        return;
+                    };
+                    let kind = if event.state.is_pressed() {
+                        ReplayInputKind::Press
+                    } else {
+                        ReplayInputKind::Release
+                    };
+                    self.process_pointer(s, kind, self.lanes_x[lane], KEY_POINTER | lane as u64, time);
                 }
-                WindowEvent::Resized(size) => self.update_game_region(*size),
                 _ => {}
             },
+            StateEvent::Pointer(_, _) if self.replay.is_some() || !self.is_judging() => {}
+            StateEvent::Pointer(pointer, time) => {
+                let x = map_screen_x_to_game_x(&self.game_rect, pointer.get_loc().x as f32);
+                let kind = match pointer.get_phase() {
+                    TouchPhase::Started => ReplayInputKind::Press,
+                    TouchPhase::Moved => ReplayInputKind::Move,
+                    TouchPhase::Ended | TouchPhase::Cancelled => ReplayInputKind::Release,
+                };
+                self.process_pointer(s, kind, x, pointer.get_id(), time);
+            }
             _ => {}
         }
     }
 
     fn switch(self: Box<Self>) -> Trans {
+        let result = BeatmapPlayResult::from_game(&self.gaming);
+        let replay = match self.replay {
+            Some(player) => {
+                let replay = player.get_replay();
+                if replay.score != result.score {
+                    log::warn!("The replay score {} is not the recorded {}", result.score, replay.score);
+                }
+                Some(replay.clone())
+            }
+            // nothing to play back for the auto play.
+            None if self.gaming.auto_play => None,
+            None => {
+                let file_name = |path: Option<&Path>| {
+                    path.and_then(Path::file_name)
+                        .map(|x| x.to_string_lossy().to_string())
+                        .unwrap_or_default()
+                };
+                let path = &self.beatmap.file_path;
+                let (song, beatmap) = (file_name(path.parent()), file_name(Some(path)));
+                match Replay::new(song, beatmap, &self.gaming) {
+                    Ok(replay) => {
+                        let to_save = replay.clone();
+                        IO_POOL.spawn_ok(async move {
+                            match to_save.save(&get_replay_dir()) {
+                                Ok(path) => log::info!("Saved the replay {:?}", path),
+                                Err(e) => log::warn!("Failed to save the replay for {:?}", e),
+                            }
+                        });
+                        Some(replay)
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to record the replay for {:?}", e);
+                        None
+                    }
+                }
+            }
+        };
         Trans::Push(Box::new(EndResultState {
-            result: BeatmapPlayResult::from_game(&self.gaming),
+            result,
             gaming: self.gaming,
+            song_info: self.song_info,
+            beatmap: self.beatmap,
+            replay,
         }))
     }
 }
@@ -4,37 +4,111 @@ mod end;
 use crate::engine::{
     GameState, LoopState, StateData, StateEvent, Trans, WaitFutureState, WaitResult,
 };
+use crate::game::beatmap::mods::{
+    PlayMods, MAX_RATE, MAX_SCROLL_SPEED, MIN_RATE, MIN_SCROLL_SPEED,
+};
+use crate::game::preview::SongPreview;
+use crate::game::replay::{get_replay_dir, Replay, REPLAY_EXT};
 use crate::game::song::{SongManager, SongManagerResourceType};
 use crate::state::play::gaming::GamingState;
 use crate::ui::song_list::SongListUi;
-use egui::{Align, Context, Frame, Layout, Pos2, Rect, UiBuilder, UiKind, UiStackInfo};
+use egui::{Align, Context, Frame, Layout, Pos2, Rect, Slider, Ui, UiBuilder, UiKind, UiStackInfo};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::SystemTime;
 use winit::keyboard::{KeyCode, PhysicalKey};
 
 pub struct PlayMenu {
     ui: SongListUi,
+    preview: Option<SongPreview>,
+    /// The song manager generation of the shown songs.
+    song_generation: u64,
+    mods: PlayMods,
+    /// Shuffle the lanes with a new seed every play.
+    random: bool,
 }
 
 impl PlayMenu {
     pub fn new() -> Self {
         Self {
             ui: Default::default(),
+            preview: None,
+            song_generation: 0,
+            mods: Default::default(),
+            random: false,
         }
     }
 
+    fn mods_ui(&mut self, ui: &mut Ui) {
+        ui.heading("Mods | 模组");
+        let mods = &mut self.mods;
+        ui.checkbox(&mut mods.auto_play, "Auto | 自动");
+        ui.checkbox(&mut mods.mirror, "Mirror | 镜像");
+        ui.checkbox(&mut self.random, "Random | 随机");
+        ui.checkbox(&mut mods.hidden, "Hidden | 隐藏");
+        ui.checkbox(&mut mods.sudden, "Sudden | 骤现");
+        ui.checkbox(&mut mods.no_fail, "NoFail | 不死");
+        ui.add(Slider::new(&mut mods.rate, MIN_RATE..=MAX_RATE).step_by(0.05).text("Rate | 速率"));
+        ui.add(
+            Slider::new(&mut mods.scroll_speed, MIN_SCROLL_SPEED..=MAX_SCROLL_SPEED)
+                .step_by(0.05)
+                .text("Scroll speed | 流速"),
+        );
+        ui.label(format!("Score x{:.2}", mods.get_multiplier()));
+    }
+
+    /// The mods to play with, the random gets a new seed.
+    fn get_play_mods(&self) -> PlayMods {
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|x| x.as_nanos() as u64)
+            .unwrap_or_default();
+        PlayMods {
+            random: self.random.then_some(seed),
+            ..self.mods
+        }
+    }
+
+    /// Pick a saved replay and play it back.
+    fn open_replay(&self, s: &mut StateData) -> Trans {
+        let path = rfd::FileDialog::new()
+            .add_filter("replay", &[REPLAY_EXT])
+            .set_directory(get_replay_dir())
+            .set_parent(&s.app.window)
+            .pick_file();
+        log::info!("Select replay result: {path:?}");
+        let Some(path) = path else {
+            return Trans::None;
+        };
+        let song_manager = Arc::clone(&s.wd.world.fetch::<SongManagerResourceType>());
+        let handle = s.app.audio.as_mut().unwrap().stream_handle.clone();
+        Trans::Push(WaitFutureState::wait_task(async move {
+            let state = Replay::load(&path).and_then(|replay| {
+                let (song_info, beatmap) = replay.find_beatmap(&song_manager)?;
+                GamingState::new_replay(handle, song_info, beatmap, replay)
+            });
+            match state {
+                Ok(state) => WaitResult::Push(Box::new(state)),
+                Err(e) => {
+                    log::warn!("Failed to open the replay {:?} for {:?}", path, e);
+                    WaitResult::Function(Box::new(|_| Trans::None))
+                }
+            }
+        }))
+    }
+
     fn update_ui(&mut self, s: &mut StateData) {
-        let songs =
-            s.wd.world
-                .get_mut::<SongManagerResourceType>()
-                .unwrap()
-                .songs
-                .iter()
-                .map(|x| x.value().clone())
-                .collect::<Vec<_>>();
+        let song_manager = s.wd.world.get_mut::<SongManagerResourceType>().unwrap();
+        let generation = song_manager.generation.load(Ordering::Acquire);
+        let songs = song_manager
+            .songs
+            .iter()
+            .map(|x| x.value().clone())
+            .collect::<Vec<_>>();
         if !songs.par_iter().any(|x| x.dirty.load(Ordering::Relaxed)) {
             self.ui.update_songs(songs);
+            self.song_generation = generation;
         }
     }
 }
@@ -42,15 +116,22 @@ impl PlayMenu {
 impl GameState for PlayMenu {
     fn start(&mut self, s: &mut StateData) -> LoopState {
         self.update_ui(s);
+        self.preview = s
+            .app
+            .audio
+            .as_ref()
+            .map(|x| SongPreview::new(x.stream_handle.clone()));
         LoopState::WAIT
     }
 
     fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
-        if self
-            .ui
-            .songs()
-            .par_iter()
-            .any(|x| x.dirty.load(Ordering::Relaxed))
+        let generation = s.wd.world.fetch::<SongManagerResourceType>().generation.load(Ordering::Acquire);
+        if generation != self.song_generation
+            || self
+                .ui
+                .songs()
+                .par_iter()
+                .any(|x| x.dirty.load(Ordering::Relaxed))
         {
             self.update_ui(s);
         }
@@ -81,6 +162,11 @@ impl GameState for PlayMenu {
                 ui.allocate_new_ui(builder, |ui| {
                     ui.vertical(|ui| {
                         ui.allocate_space((0.0, 100.0).into());
+                        self.mods_ui(ui);
+                        ui.separator();
+                        if ui.button("Open replay | 打开回放").clicked() {
+                            tran = self.open_replay(s);
+                        }
                     });
                 });
 
@@ -95,12 +181,12 @@ impl GameState for PlayMenu {
                 ui.allocate_new_ui(builder, |ui| {
                     let response = self.ui.ui(ui);
                     if let Some(result) = response.result {
-                        if let Some(beatmap) = &result.beatmap {
+                        if let Some(beatmap) = result.beatmap {
                             let song_info = result.song;
-                            let beatmap = beatmap.song_beatmap_file.clone();
                             let handle = s.app.audio.as_mut().unwrap().stream_handle.clone();
+                            let mods = self.get_play_mods();
                             tran = Trans::Push(WaitFutureState::wait_task(async move {
-                                let state = GamingState::new(handle, &song_info, beatmap);
+                                let state = GamingState::new(handle, song_info, beatmap, mods);
                                 match state {
                                     Ok(state) => {
                                         let state = Box::new(state);
@@ -117,6 +203,14 @@ impl GameState for PlayMenu {
                 });
             });
 
+        if let Some(preview) = &mut self.preview {
+            // stop the preview when entering the other state.
+            match (&tran, self.ui.selected_song()) {
+                (Trans::None, Some(song)) => preview.play(song),
+                _ => preview.stop(),
+            }
+        }
+
         tran
     }
 
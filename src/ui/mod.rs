@@ -1 +1,2 @@
-pub mod song_list;
\ No newline at end of file
+pub mod song_filter;
+pub mod song_list;
@@ -0,0 +1,231 @@
+//! Search, filter and sort the songs by the loaded beatmaps, no file is read here.
+
+use crate::game::beatmap::{MapRule, SongBeatmapInfo};
+use crate::game::song::SongInfo;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum SongSort {
+    #[default]
+    Title,
+    Artist,
+    /// The max note count of the beatmaps.
+    NoteCount,
+    /// The max duration of the beatmaps.
+    Length,
+    /// The max bpm of the beatmaps.
+    Bpm,
+    Added,
+}
+
+impl SongSort {
+    pub const ALL: [SongSort; 6] = [
+        SongSort::Title,
+        SongSort::Artist,
+        SongSort::NoteCount,
+        SongSort::Length,
+        SongSort::Bpm,
+        SongSort::Added,
+    ];
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            SongSort::Title => "Title | 标题",
+            SongSort::Artist => "Artist | 艺术家",
+            SongSort::NoteCount => "Notes | 物量",
+            SongSort::Length => "Length | 长度",
+            SongSort::Bpm => "BPM",
+            SongSort::Added => "Added | 添加时间",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SongFilter {
+    /// The words split by whitespace, every word should be found in the title, artist, creator,
+    /// source or tags, case insensitive.
+    pub search: String,
+    /// Only the songs have the beatmap of the rule.
+    pub rule: Option<MapRule>,
+    pub min_notes: Option<usize>,
+    pub max_notes: Option<usize>,
+    pub sort: SongSort,
+    pub descending: bool,
+}
+
+/// The artist in the song metadata, or the artist of the first beatmap.
+pub fn get_song_artist(song: &SongInfo) -> &str {
+    if song.metadata.artist.is_empty() {
+        song.maps
+            .first()
+            .map(|x| x.metadata.artist.as_str())
+            .unwrap_or_default()
+    } else {
+        &song.metadata.artist
+    }
+}
+
+fn max_of<T: Default>(
+    song: &SongInfo,
+    f: impl Fn(&SongBeatmapInfo) -> T,
+    cmp: impl Fn(&T, &T) -> Ordering,
+) -> T {
+    song.maps.iter().map(f).max_by(cmp).unwrap_or_default()
+}
+
+impl SongFilter {
+    fn is_beatmap_filtered(&self) -> bool {
+        self.rule.is_some() || self.min_notes.is_some() || self.max_notes.is_some()
+    }
+
+    fn matches_beatmap(&self, beatmap: &SongBeatmapInfo) -> bool {
+        let notes = beatmap.stats.get_note_count();
+        self.rule.map_or(true, |x| beatmap.rule == x)
+            && self.min_notes.map_or(true, |x| notes >= x)
+            && self.max_notes.map_or(true, |x| notes <= x)
+    }
+
+    fn matches_search(&self, song: &SongInfo) -> bool {
+        let mut fields = vec![
+            song.get_show_title().to_lowercase(),
+            get_song_artist(song).to_lowercase(),
+        ];
+        for beatmap in &song.maps {
+            let metadata = &beatmap.metadata;
+            fields.extend(
+                [
+                    &metadata.title,
+                    &metadata.artist,
+                    &metadata.creator,
+                    &metadata.source,
+                ]
+                .into_iter()
+                .map(|x| x.to_lowercase()),
+            );
+            fields.extend(metadata.tags.split(',').map(|x| x.trim().to_lowercase()));
+        }
+        self.search
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .all(|word| fields.iter().any(|x| x.contains(&word)))
+    }
+
+    pub fn matches(&self, song: &SongInfo) -> bool {
+        let beatmap_matched = if self.is_beatmap_filtered() {
+            song.maps.iter().any(|x| self.matches_beatmap(x))
+        } else {
+            true
+        };
+        beatmap_matched && self.matches_search(song)
+    }
+
+    fn compare(&self, a: &SongInfo, b: &SongInfo) -> Ordering {
+        let title = |x: &SongInfo| x.get_show_title().to_lowercase();
+        let result = match self.sort {
+            SongSort::Title => Ordering::Equal,
+            SongSort::Artist => get_song_artist(a)
+                .to_lowercase()
+                .cmp(&get_song_artist(b).to_lowercase()),
+            SongSort::NoteCount => {
+                let notes = |x| max_of(x, |x| x.stats.get_note_count(), Ord::cmp);
+                notes(a).cmp(&notes(b))
+            }
+            SongSort::Length => {
+                let length = |x| max_of(x, |x| x.stats.get_duration(), Ord::cmp);
+                length(a).cmp(&length(b))
+            }
+            SongSort::Bpm => {
+                let bpm = |x| max_of(x, |x| Into::<f32>::into(x.stats.max_bpm), f32::total_cmp);
+                bpm(a).total_cmp(&bpm(b))
+            }
+            SongSort::Added => a.added.cmp(&b.added),
+        }
+        .then_with(|| title(a).cmp(&title(b)));
+        if self.descending {
+            result.reverse()
+        } else {
+            result
+        }
+    }
+
+    /// The matched songs in the sorted order.
+    pub fn apply(&self, songs: &[Arc<SongInfo>]) -> Vec<Arc<SongInfo>> {
+        let mut result = songs
+            .iter()
+            .filter(|x| self.matches(x))
+            .cloned()
+            .collect::<Vec<_>>();
+        result.sort_by(|a, b| self.compare(a, b));
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::beatmap::file::SongBeatmapFile;
+    use crate::game::beatmap::{MapRule, SongBeatmapInfo};
+    use crate::game::note::{NormalNote, NoteHitType};
+    use crate::game::song::{SongInfo, SongMetadata};
+    use crate::ui::song_filter::{SongFilter, SongSort};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    fn song(title: &str, tags: &str, rule: MapRule, notes: usize, added: u64) -> Arc<SongInfo> {
+        let mut beatmap = SongBeatmapFile::new(title.to_string());
+        beatmap.rule = rule;
+        beatmap.metadata.tags = tags.to_string();
+        beatmap.normal_notes = (0..notes)
+            .map(|i| NormalNote {
+                x: 0.0,
+                width: 0.25,
+                time: i as i64 * 100,
+                note_type: NoteHitType::Click,
+                timing_group: 0,
+            })
+            .collect();
+        Arc::new(SongInfo {
+            bgm_file: Default::default(),
+            title: title.to_string(),
+            root: Default::default(),
+            metadata: SongMetadata::default(),
+            maps: vec![SongBeatmapInfo::new(Default::default(), &beatmap)],
+            added: SystemTime::UNIX_EPOCH + Duration::from_secs(added),
+            dirty: Default::default(),
+        })
+    }
+
+    fn titles(songs: &[Arc<SongInfo>]) -> Vec<&str> {
+        songs.iter().map(|x| x.title.as_str()).collect()
+    }
+
+    #[test]
+    fn test_filter() {
+        let songs = [
+            song("Beta", "piano,slow", MapRule::Falling, 10, 3),
+            song("alpha", "rock", MapRule::FourKey, 30, 1),
+            song("Gamma", "Piano Rock", MapRule::FourKey, 20, 2),
+        ];
+        let mut filter = SongFilter::default();
+        assert_eq!(titles(&filter.apply(&songs)), ["alpha", "Beta", "Gamma"]);
+
+        filter.search = "PIANO".into();
+        assert_eq!(titles(&filter.apply(&songs)), ["Beta", "Gamma"]);
+        filter.search = "piano rock".into();
+        assert_eq!(titles(&filter.apply(&songs)), ["Gamma"]);
+
+        filter.search.clear();
+        filter.rule = Some(MapRule::FourKey);
+        filter.sort = SongSort::NoteCount;
+        assert_eq!(titles(&filter.apply(&songs)), ["Gamma", "alpha"]);
+        filter.max_notes = Some(25);
+        assert_eq!(titles(&filter.apply(&songs)), ["Gamma"]);
+
+        filter = SongFilter {
+            sort: SongSort::Added,
+            descending: true,
+            ..Default::default()
+        };
+        assert_eq!(titles(&filter.apply(&songs)), ["Beta", "Gamma", "alpha"]);
+    }
+}
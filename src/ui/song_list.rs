@@ -1,13 +1,17 @@
-use crate::game::beatmap::SongBeatmapInfo;
+use crate::game::beatmap::{MapRule, SongBeatmapInfo};
 use crate::game::song::SongInfo;
-use egui::{Button, Color32, NumExt, RichText, ScrollArea, Ui, Vec2};
+use crate::ui::song_filter::{SongFilter, SongSort};
+use egui::{Button, Color32, ComboBox, DragValue, NumExt, RichText, ScrollArea, TextEdit, Ui, Vec2};
 use std::cell::Cell;
 use std::sync::Arc;
 
 #[derive(Default)]
 pub struct SongListUi {
     allow_select_song: bool,
+    all_songs: Vec<Arc<SongInfo>>,
+    /// The songs matched the filter in order.
     songs: Vec<Arc<SongInfo>>,
+    filter: SongFilter,
 
     song_select: Cell<usize>,
     beatmap_select: Cell<usize>,
@@ -28,12 +32,27 @@ pub struct SongListUiResponse {
 
 impl SongListUi {
     pub fn update_songs(&mut self, songs: Vec<Arc<SongInfo>>) {
-        self.songs = songs;
-        self.songs.sort_by(|x, y| x.title.cmp(&y.title));
+        self.all_songs = songs;
+        self.apply_filter();
     }
 
+    /// Filter and sort the songs again, keep the selected song if still shown.
+    fn apply_filter(&mut self) {
+        let selected = self.selected_song().map(|x| x.bgm_file.clone());
+        self.songs = self.filter.apply(&self.all_songs);
+        let idx = selected
+            .and_then(|selected| self.songs.iter().position(|x| x.bgm_file == selected))
+            .unwrap_or(0);
+        self.song_select.set(idx);
+    }
+
+    /// All the songs, including the filtered out.
     pub fn songs(&self) -> &Vec<Arc<SongInfo>> {
-        &self.songs
+        &self.all_songs
+    }
+
+    pub fn selected_song(&self) -> Option<&Arc<SongInfo>> {
+        self.songs.get(self.song_select.get())
     }
 
     pub fn render_beatmap(&self, ui: &mut Ui, song_idx: usize, idx: usize) -> Option<EnterResult> {
@@ -43,7 +62,12 @@ impl SongListUi {
         #[cfg(debug_assertions)]
         let old_y = ui.next_widget_position().y;
 
-        let button = Button::new(beatmap.song_beatmap_file.get_show_name()).fill(
+        let text = format!(
+            "{}\n★ {:.2}",
+            beatmap.metadata.get_show_name(),
+            beatmap.difficulty.rating
+        );
+        let button = Button::new(text).fill(
             if self.beatmap_select.get() == idx {
                 Color32::BLUE
             } else {
@@ -86,7 +110,12 @@ impl SongListUi {
         let old_y = ui.next_widget_position().y;
 
         let selected = self.song_select.get() == idx;
-        let button = Button::new(RichText::new(&song.title).color(Color32::WHITE))
+        let text = if song.metadata.artist.is_empty() {
+            song.get_show_title().to_string()
+        } else {
+            format!("{}\n{}", song.get_show_title(), song.metadata.artist)
+        };
+        let button = Button::new(RichText::new(text).color(Color32::WHITE))
             .selected(selected)
             .fill(if self.song_select.get() == idx {
                 Color32::LIGHT_BLUE
@@ -129,8 +158,45 @@ impl SongListUi {
         result
     }
 
+    fn filter_ui(&mut self, ui: &mut Ui) {
+        let filter = &mut self.filter;
+        let old = filter.clone();
+        ui.horizontal(|ui| {
+            ui.add(TextEdit::singleline(&mut filter.search).hint_text("Search | 搜索"));
+            ComboBox::from_id_salt("song_sort")
+                .selected_text(filter.sort.get_name())
+                .show_ui(ui, |ui| {
+                    for sort in SongSort::ALL {
+                        ui.selectable_value(&mut filter.sort, sort, sort.get_name());
+                    }
+                });
+            let order = if filter.descending { "↓" } else { "↑" };
+            ui.toggle_value(&mut filter.descending, order);
+        });
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut filter.rule, None, "All | 全部");
+            for rule in MapRule::all() {
+                ui.selectable_value(&mut filter.rule, Some(rule), rule.get_name());
+            }
+            ui.label("Notes | 物量:");
+            for value in [&mut filter.min_notes, &mut filter.max_notes] {
+                let mut enabled = value.is_some();
+                if ui.checkbox(&mut enabled, "").changed() {
+                    *value = enabled.then_some(0);
+                }
+                if let Some(value) = value {
+                    ui.add(DragValue::new(value));
+                }
+            }
+        });
+        if *filter != old {
+            self.apply_filter();
+        }
+    }
+
     pub fn ui(&mut self, ui: &mut Ui) -> SongListUiResponse {
         let mut response = SongListUiResponse::default();
+        self.filter_ui(ui);
         ScrollArea::new([false, true])
             .auto_shrink(false)
             .max_height(f32::INFINITY)